@@ -0,0 +1,223 @@
+// Measures response time recording overhead of the blocking engine. The
+// real engine first runs 1000 connections against a local keep-alive HTTP
+// server, every worker recording into its own recorder flushed into storage.
+// The same number of samples per connection is then recorded again without
+// any HTTP, once into per worker recorders merged into storage the way
+// engine does and once into a single mutex guarded recorder merged into
+// storage just as often, so recording cost isn't hidden behind request
+// latency. Both modes record samples the same way, they only differ in the
+// lock every sample takes.
+//
+// Recording overhead is reported as percentiles since a thread preempted in
+// the middle of a measurement skews the mean on machines with few cores.
+//
+// Run it with `cargo bench --bench recorder`.
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Barrier, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use cicadas::{
+    app::Arguments,
+    engine::Engine,
+    errors::AppResult,
+    recorder::{Recorder, Sample},
+    scheduler::Scheduler,
+    storage::Storage,
+};
+use hdrhistogram::Histogram;
+use reqwest::Method;
+
+const CONNECTIONS: usize = 1000;
+const DURATION: Duration = Duration::from_secs(5);
+const SIGNIFICANT_FIGURES: u8 = 3;
+
+#[derive(Clone, Copy)]
+enum Mode {
+    GlobalMutex,
+    PerWorker,
+}
+
+impl Mode {
+    fn label(&self) -> &str {
+        match self {
+            Self::GlobalMutex => "global mutex",
+            Self::PerWorker => "per worker",
+        }
+    }
+}
+
+fn main() {
+    let address = spawn_server();
+    let arguments = Arc::new(Arguments {
+        duration: Some(DURATION.as_secs()),
+        connections: CONNECTIONS,
+        significant_figures: SIGNIFICANT_FIGURES,
+        quiet: true,
+        ..Arguments::new(format!("http://{}/", address), Method::GET)
+    });
+
+    println!(
+        "{} connections against {} for {} seconds",
+        CONNECTIONS,
+        arguments.target,
+        DURATION.as_secs()
+    );
+
+    let requests = run_engine(arguments.clone()).expect("engine failed");
+    let samples = (requests / CONNECTIONS as u64).max(1);
+
+    println!(
+        "{:>14}: {:>10.0} req/s, replaying {} samples per connection",
+        "engine",
+        requests as f64 / DURATION.as_secs_f64(),
+        samples
+    );
+
+    for mode in [Mode::GlobalMutex, Mode::PerWorker] {
+        let recording_times =
+            record(&arguments, mode, samples).unwrap_or_else(|_| panic!("{} failed", mode.label()));
+
+        println!(
+            "{:>14}: recording overhead p50 {:>6} ns, p99 {:>6} ns, p99.9 {:>6} ns",
+            mode.label(),
+            recording_times.value_at_percentile(50.0),
+            recording_times.value_at_percentile(99.0),
+            recording_times.value_at_percentile(99.9)
+        );
+    }
+}
+
+fn run_engine(arguments: Arc<Arguments>) -> AppResult<u64> {
+    let storage = Arc::new(Storage::from_arguments(&arguments)?);
+    let scheduler = Arc::new(Scheduler::from_arguments(&arguments));
+
+    Engine::Blocking.run(arguments, storage.clone(), scheduler, None, None)?;
+
+    Ok(storage.get_response_times().get_total_requests_count()? as u64)
+}
+
+// Every connection records its samples at once, timing each recording along
+// with flushes into storage that come due meanwhile.
+fn record(arguments: &Arguments, mode: Mode, samples: u64) -> AppResult<Histogram<u64>> {
+    let storage = Arc::new(Storage::from_arguments(arguments)?);
+    let global_recorder = Arc::new(Mutex::new(Recorder::new(SIGNIFICANT_FIGURES)));
+    let barrier = Arc::new(Barrier::new(CONNECTIONS));
+    let mut handles = Vec::with_capacity(CONNECTIONS);
+
+    for worker in 0..CONNECTIONS {
+        let storage = storage.clone();
+        let global_recorder = global_recorder.clone();
+        let barrier = barrier.clone();
+
+        handles.push(thread::spawn(move || -> AppResult<Histogram<u64>> {
+            let mut recorder = Recorder::new(SIGNIFICANT_FIGURES);
+            let mut recording_times = Recorder::new_histogram(SIGNIFICANT_FIGURES)?;
+
+            barrier.wait();
+
+            for index in 0..samples {
+                let sample = get_sample(worker, index);
+                let recording_start_time = Instant::now();
+
+                match mode {
+                    Mode::GlobalMutex => {
+                        let mut global_recorder =
+                            global_recorder.lock().expect("global recorder poisoned");
+
+                        add_sample(&storage, &mut global_recorder, &sample)?;
+                    }
+                    Mode::PerWorker => add_sample(&storage, &mut recorder, &sample)?,
+                }
+
+                recording_times
+                    .saturating_record((Instant::now() - recording_start_time).as_nanos() as u64);
+            }
+
+            if let Mode::PerWorker = mode {
+                storage.merge(&recorder)?;
+            }
+
+            Ok(recording_times)
+        }));
+    }
+
+    let mut recording_times = Recorder::new_histogram(SIGNIFICANT_FIGURES)?;
+
+    for handle in handles {
+        recording_times
+            .add(&handle.join().expect("worker panicked")?)
+            .expect("can't merge recording times");
+    }
+
+    if let Mode::GlobalMutex = mode {
+        storage.merge(&global_recorder.lock().expect("global recorder poisoned"))?;
+    }
+
+    Ok(recording_times)
+}
+
+fn add_sample(storage: &Storage, recorder: &mut Recorder, sample: &Sample) -> AppResult<()> {
+    recorder.add_sample(sample)?;
+
+    if recorder.is_flush_due() {
+        storage.merge(recorder)?;
+        *recorder = Recorder::new(SIGNIFICANT_FIGURES);
+    }
+
+    Ok(())
+}
+
+fn get_sample(worker: usize, index: u64) -> Sample {
+    Sample {
+        worker,
+        sent_bytes: Some(64),
+        received_bytes: Some(2),
+        response_time: Duration::from_micros(500 + index % 1000),
+        second: index / 1000,
+        ..Sample::default()
+    }
+}
+
+fn spawn_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("can't bind local server");
+    let address = listener.local_addr().expect("can't read local address");
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            thread::spawn(move || handle_connection(stream));
+        }
+    });
+
+    address.to_string()
+}
+
+fn handle_connection(stream: TcpStream) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+
+        match reader.read_line(&mut line) {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {}
+        }
+
+        if line == "\r\n"
+            && writer
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok")
+                .is_err()
+        {
+            return;
+        }
+    }
+}
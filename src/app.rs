@@ -1,21 +1,84 @@
-use clap::{arg, command, value_parser, ArgMatches, Command};
+use std::{path::PathBuf, process, thread};
+
+use clap::{
+    arg, builder::RangedU64ValueParser, command, parser::ValueSource, value_parser, Arg, ArgAction,
+    ArgMatches, Command,
+};
 use reqwest::{
     header::{HeaderMap, HeaderName, HeaderValue},
     Method,
 };
 
-use crate::errors::{AppResult, ErrorType};
+use crate::{
+    assertion::Assertion,
+    capture::CaptureFilter,
+    config::Config,
+    engine::Engine,
+    errors::{AppResult, ErrorType},
+    profile::Profile,
+    scenario::Scenario,
+    threshold::Threshold,
+};
 
 #[derive(Debug, Clone)]
 pub struct Arguments {
     pub target: String,
     pub method: Method,
     pub proxy: Option<String>,
-    pub duration: u64,
+    pub duration: Option<u64>,
+    pub requests: Option<u64>,
+    pub warmup: u64,
+    pub rate: Option<u64>,
     pub connections: usize,
     pub payload: Option<String>,
     pub headers: Option<HeaderMap>,
     pub timeout: u64,
+    pub significant_figures: u8,
+    pub engine: Engine,
+    pub threads: usize,
+    pub profile: Option<Profile>,
+    pub quiet: bool,
+    pub output: Vec<PathBuf>,
+    pub request_log: Option<PathBuf>,
+    pub save_responses: Option<PathBuf>,
+    pub save_filter: CaptureFilter,
+    pub assertions: Vec<Assertion>,
+    pub thresholds: Vec<Threshold>,
+    pub scenario: Option<Scenario>,
+}
+
+impl Arguments {
+    // Arguments with the same defaults command line uses, for running load
+    // tests without parsing a command line.
+    pub fn new(target: String, method: Method) -> Self {
+        Self {
+            target,
+            method,
+            proxy: None,
+            duration: None,
+            requests: None,
+            warmup: 0,
+            rate: None,
+            connections: 1,
+            payload: None,
+            headers: None,
+            timeout: 5,
+            significant_figures: 3,
+            engine: Engine::Blocking,
+            threads: thread::available_parallelism()
+                .map(|threads| threads.get())
+                .unwrap_or(1),
+            profile: None,
+            quiet: false,
+            output: Vec::new(),
+            request_log: None,
+            save_responses: None,
+            save_filter: CaptureFilter::All,
+            assertions: Vec::new(),
+            thresholds: Vec::new(),
+            scenario: None,
+        }
+    }
 }
 
 pub struct App {
@@ -23,69 +86,15 @@ pub struct App {
     matches: ArgMatches,
 }
 
+impl Default for App {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl App {
     pub fn new() -> Self {
-        let command = command!()
-            .about("Cicadas is a fast multi threaded HTTP load testing and benchmarking tool.")
-            .version("1.0.0")
-            .arg(
-                arg!(
-                    -t --target <target> "Target URL for applying load test"
-                )
-                .required(true)
-                .value_parser(value_parser!(String)),
-            )
-            .arg(
-                arg!(
-                    -m --method <method> "HTTP method."
-                )
-                .required(true)
-                .value_parser(value_parser!(String)),
-            )
-            .arg(
-                arg!(
-                    -P --proxy <proxy> "Optional Proxy URL to use when testing target"
-                )
-                .required(false)
-                .value_parser(value_parser!(String)),
-            )
-            .arg(
-                arg!(
-                    -d --duration <duration> "Load test duration in seconds"
-                )
-                .required(true)
-                .value_parser(value_parser!(u64)),
-            )
-            .arg(
-                arg!(
-                    -c --connections <connections> "Connections count to open and map with OS threads"
-                )
-                .required(true)
-                .value_parser(value_parser!(usize)),
-            )
-            .arg(
-                arg!(
-                    -p --payload <payload> "Optional body payload to pass to the target"
-                )
-                .required(false)
-                .value_parser(value_parser!(String)),
-            )
-            .arg(
-                arg!(
-                    -H --headers <headers> "Optional HTTP headers"
-                )
-                .num_args(0..=255)
-                .required(false)
-                .value_parser(value_parser!(String)),
-            )
-            .arg(
-                arg!(
-                    -T --timeout <timeout> "Optional request timeout duration in seconds"
-                )
-                .required(false)
-                .default_value("5")
-                .value_parser(value_parser!(u64)),
-            );
+        let command = Self::get_command();
 
         Self {
             command: command.clone(),
@@ -93,9 +102,176 @@ impl App {
         }
     }
 
+    fn get_command() -> Command {
+        command!()
+            .about("Cicadas is a fast multi threaded HTTP load testing and benchmarking tool.")
+            .version("1.0.0")
+            .args(Self::get_args())
+            .mut_arg("target", |arg| arg.required(true))
+            .mut_arg("method", |arg| arg.required(true))
+            .mut_arg("duration", |arg| {
+                arg.required_unless_present_any(["profile", "profile-file", "requests"])
+            })
+            .mut_arg("connections", |arg| {
+                arg.required_unless_present_any(["profile", "profile-file"])
+            })
+            .mut_arg("save-filter", |arg| arg.requires("save-responses"))
+            .subcommand_negates_reqs(true)
+            .args_conflicts_with_subcommands(true)
+            .subcommand(
+                Command::new("run")
+                    .about("Run load test described in a TOML or YAML config file, options passed along override file values and relative paths in the file are resolved against its directory")
+                    .arg(
+                        arg!(
+                            <config> "Config file path, .toml, .yaml or .yml"
+                        )
+                        .value_parser(value_parser!(PathBuf)),
+                    )
+                    .args(Self::get_args()),
+            )
+    }
+
+    // Options shared by plain command line usage and `run` subcommand, so any
+    // of them can override a config file value.
+    fn get_args() -> Vec<Arg> {
+        vec![
+            arg!(
+                -t --target <target> "Target URL for applying load test"
+            )
+            .required(false)
+            .value_parser(value_parser!(String)),
+            arg!(
+                -m --method <method> "HTTP method."
+            )
+            .required(false)
+            .value_parser(value_parser!(String)),
+            arg!(
+                -P --proxy <proxy> "Optional Proxy URL to use when testing target"
+            )
+            .required(false)
+            .value_parser(value_parser!(String)),
+            arg!(
+                -d --duration <duration> "Load test duration in seconds"
+            )
+            .required(false)
+            .conflicts_with_all(["profile", "profile-file"])
+            .value_parser(value_parser!(u64)),
+            arg!(
+                -n --requests <requests> "Optional total requests count to send across all connections"
+            )
+            .required(false)
+            .value_parser(value_parser!(u64).range(1..)),
+            arg!(
+                -w --warmup <warmup> "Optional warm-up duration in seconds, requests sent during it are excluded from results"
+            )
+            .required(false)
+            .default_value("0")
+            .value_parser(value_parser!(u64)),
+            arg!(
+                -r --rate <rate> "Optional constant request rate per second to distribute across connections"
+            )
+            .required(false)
+            .value_parser(value_parser!(u64).range(1..)),
+            arg!(
+                -c --connections <connections> "Concurrent connections, OS threads for the blocking engine or tasks for the async engine"
+            )
+            .required(false)
+            .conflicts_with_all(["profile", "profile-file"])
+            .value_parser(value_parser!(usize)),
+            arg!(
+                -p --payload <payload> "Optional body payload to pass to the target"
+            )
+            .required(false)
+            .value_parser(value_parser!(String)),
+            arg!(
+                -H --headers <headers> "Optional HTTP headers"
+            )
+            .num_args(0..=255)
+            .required(false)
+            .value_parser(value_parser!(String)),
+            arg!(
+                -T --timeout <timeout> "Optional request timeout duration in seconds"
+            )
+            .required(false)
+            .default_value("5")
+            .value_parser(value_parser!(u64)),
+            arg!(
+                -s --"significant-figures" <significant_figures> "Optional latency histogram precision in significant figures"
+            )
+            .required(false)
+            .default_value("3")
+            .value_parser(value_parser!(u8).range(1..=5)),
+            arg!(
+                -e --engine <engine> "Optional load engine, blocking maps each connection to an OS thread and async drives connections over runtime threads"
+            )
+            .required(false)
+            .default_value("blocking")
+            .value_parser(value_parser!(String)),
+            arg!(
+                --threads <threads> "Optional runtime threads count for async engine, default is available CPU cores"
+            )
+            .required(false)
+            .value_parser(RangedU64ValueParser::<usize>::new().range(1..)),
+            arg!(
+                --profile <profile> "Optional load profile stages separated by comma, e.g. ramp:30:100,hold:60:100"
+            )
+            .required(false)
+            .conflicts_with("profile-file")
+            .value_parser(value_parser!(String)),
+            arg!(
+                --"profile-file" <profile_file> "Optional file path to read load profile stages from, one stage per line"
+            )
+            .required(false)
+            .value_parser(value_parser!(PathBuf)),
+            arg!(
+                -q --quiet "Optional flag to disable live progress while load test is running"
+            )
+            .required(false),
+            arg!(
+                -o --output <output> "Optional file path to write report to, format is picked by file extension. can be used multiple times"
+            )
+            .required(false)
+            .action(ArgAction::Append)
+            .value_parser(value_parser!(PathBuf)),
+            arg!(
+                --"request-log" <request_log> "Optional file path to log every request to, as .csv or .jsonl"
+            )
+            .required(false)
+            .value_parser(value_parser!(PathBuf)),
+            arg!(
+                --"save-responses" <save_responses> "Optional file or directory path to store target responses"
+            )
+            .required(false)
+            .value_parser(value_parser!(PathBuf)),
+            arg!(
+                --"save-filter" <save_filter> "Optional filter for stored responses, all, non-2xx or every:<N>"
+            )
+            .required(false)
+            .default_value("all")
+            .value_parser(value_parser!(String)),
+            arg!(
+                -a --assert <assert> "Optional assertion every response has to pass, e.g. status:200 or json:$.ok=true. can be used multiple times"
+            )
+            .required(false)
+            .action(ArgAction::Append)
+            .value_parser(value_parser!(String)),
+            arg!(
+                --threshold <threshold> "Optional pass/fail condition on results, e.g. p99<250ms, error_rate<1% or rps>1000. can be used multiple times"
+            )
+            .required(false)
+            .action(ArgAction::Append)
+            .value_parser(value_parser!(String)),
+        ]
+    }
+
     pub fn throw_error(&mut self, error_type: ErrorType) {
         let (kind, message) = error_type.to_command_error();
 
+        if let Some(exit_code) = error_type.get_exit_code() {
+            eprintln!("error: {}", message);
+            process::exit(exit_code);
+        }
+
         self.command.error(kind, message).exit()
     }
 
@@ -109,14 +285,7 @@ impl App {
                 return Err(ErrorType::InvalidHeaderStructure);
             }
 
-            let key: HeaderName = match splitted_header[0].parse() {
-                Ok(value) => value,
-                Err(_) => return Err(ErrorType::InvalidHeaderName),
-            };
-            let value: HeaderValue = match splitted_header[1].parse() {
-                Ok(value) => value,
-                Err(_) => return Err(ErrorType::InvalidHeaderValue),
-            };
+            let (key, value) = Self::parse_header(splitted_header[0], splitted_header[1])?;
 
             headers_map.insert(key, value);
         }
@@ -124,6 +293,19 @@ impl App {
         Ok(Some(headers_map))
     }
 
+    pub fn parse_header(name: &str, value: &str) -> AppResult<(HeaderName, HeaderValue)> {
+        let key: HeaderName = match name.parse() {
+            Ok(value) => value,
+            Err(_) => return Err(ErrorType::InvalidHeaderName),
+        };
+        let value: HeaderValue = match value.parse() {
+            Ok(value) => value,
+            Err(_) => return Err(ErrorType::InvalidHeaderValue),
+        };
+
+        Ok((key, value))
+    }
+
     pub fn parse_method(method: String) -> AppResult<Method> {
         let method = method.to_uppercase();
 
@@ -138,26 +320,121 @@ impl App {
         }
     }
 
+    pub fn parse_engine(engine: String) -> AppResult<Engine> {
+        let engine = engine.to_lowercase();
+
+        match engine.as_str() {
+            "blocking" => Ok(Engine::Blocking),
+            "async" => Ok(Engine::Async),
+            _ => Err(ErrorType::InvalidEngine),
+        }
+    }
+
+    fn is_command_line(matches: &ArgMatches, id: &str) -> bool {
+        matches!(matches.value_source(id), Some(ValueSource::CommandLine))
+    }
+
+    // Values passed on command line win over config file values, which win
+    // over command line defaults.
+    fn get_value<T>(matches: &ArgMatches, id: &str, config_value: Option<T>) -> Option<T>
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        if Self::is_command_line(matches, id) {
+            return matches.get_one::<T>(id).cloned();
+        }
+
+        config_value.or_else(|| matches.get_one::<T>(id).cloned())
+    }
+
+    fn get_values<T>(matches: &ArgMatches, id: &str, config_values: Option<Vec<T>>) -> Vec<T>
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        if !Self::is_command_line(matches, id) {
+            if let Some(config_values) = config_values {
+                return config_values;
+            }
+        }
+
+        match matches.get_many::<T>(id) {
+            Some(values) => values.cloned().collect(),
+            None => Vec::with_capacity(0),
+        }
+    }
+
+    fn get_required<T>(matches: &ArgMatches, id: &str, config_value: Option<T>) -> AppResult<T>
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        match Self::get_value(matches, id, config_value) {
+            Some(value) => Ok(value),
+            None => Err(Config::invalid(format!(
+                "{} is required, provide it in config file or with --{}.",
+                id, id
+            ))),
+        }
+    }
+
     pub fn get_arguments(&self) -> AppResult<Arguments> {
-        let target = self.matches.get_one::<String>("target").unwrap().to_owned();
-        let raw_method = self.matches.get_one::<String>("method").unwrap().to_owned();
+        let (matches, config) = match self.matches.subcommand() {
+            Some(("run", matches)) => (
+                matches,
+                Config::from_file(matches.get_one::<PathBuf>("config").unwrap())?,
+            ),
+            _ => (&self.matches, Config::default()),
+        };
+
+        let target = Self::get_required(matches, "target", config.target)?;
+        let raw_method = Self::get_required(matches, "method", config.method)?;
         let method = Self::parse_method(raw_method)?;
-        let proxy = match self.matches.get_one::<String>("proxy").to_owned() {
-            Some(proxy) => Some(proxy.to_owned()),
-            None => None,
+        let proxy = Self::get_value(matches, "proxy", config.proxy);
+        // A flat duration or connections count on command line replaces load
+        // profile of config file, like it would conflict on command line.
+        let (raw_profile, profile_file) = if ["duration", "connections", "profile", "profile-file"]
+            .iter()
+            .any(|id| Self::is_command_line(matches, id))
+        {
+            (
+                matches.get_one::<String>("profile").cloned(),
+                matches.get_one::<PathBuf>("profile-file").cloned(),
+            )
+        } else {
+            (
+                config.profile.map(|stages| stages.join(",")),
+                config.profile_file,
+            )
+        };
+        let profile = match raw_profile {
+            Some(stages) => Some(Profile::parse(&stages)?),
+            None => match profile_file {
+                Some(path) => Some(Profile::from_file(path)?),
+                None => None,
+            },
         };
-        let duration = self.matches.get_one::<u64>("duration").unwrap().to_owned();
-        let timeout = self.matches.get_one::<u64>("timeout").unwrap().to_owned();
-        let connections = self
-            .matches
-            .get_one::<usize>("connections")
-            .unwrap()
-            .to_owned();
-        let payload = match self.matches.get_one::<String>("payload").to_owned() {
-            Some(payload) => Some(payload.to_owned()),
-            None => None,
+        let requests = Self::get_value(matches, "requests", config.requests);
+        let duration = match &profile {
+            Some(profile) => Some(profile.get_duration()),
+            None => Self::get_value(matches, "duration", config.duration),
+        };
+
+        if duration.is_none() && requests.is_none() {
+            return Err(Config::invalid(
+                "duration, requests or a load profile is required, provide one in config file or with --duration, --requests or --profile.".to_owned(),
+            ));
+        }
+
+        let warmup = Self::get_required(matches, "warmup", config.warmup)?;
+        let rate = Self::get_value(matches, "rate", config.rate);
+        let timeout = Self::get_required(matches, "timeout", config.timeout)?;
+        let significant_figures =
+            Self::get_required(matches, "significant-figures", config.significant_figures)?;
+        let connections = match &profile {
+            Some(profile) => profile.get_max_connections(),
+            None => Self::get_required(matches, "connections", config.connections)?,
         };
-        let raw_headers: Vec<String> = match self.matches.get_many("headers") {
+        let payload = Self::get_value(matches, "payload", config.payload);
+        let raw_headers: Vec<String> = match matches.get_many("headers") {
             Some(headers) => {
                 let headers: Vec<String> = headers.cloned().collect();
 
@@ -165,17 +442,212 @@ impl App {
             }
             None => Vec::with_capacity(0),
         };
-        let headers = Self::parse_headers(raw_headers)?;
+        // Config file headers are merged with command line ones, command line
+        // wins when both set the same header.
+        let mut headers = HeaderMap::with_capacity(config.headers.len() + raw_headers.len());
+
+        for (name, value) in &config.headers {
+            let (key, value) = Self::parse_header(name, value)?;
+
+            headers.insert(key, value);
+        }
+        if let Some(raw_headers) = Self::parse_headers(raw_headers)? {
+            headers.extend(raw_headers);
+        }
+
+        let headers = Some(headers);
+        let raw_engine = Self::get_required(matches, "engine", config.engine)?;
+        let engine = Self::parse_engine(raw_engine)?;
+        let threads = match Self::get_value(matches, "threads", config.threads) {
+            Some(threads) => threads,
+            None => thread::available_parallelism()
+                .map(|threads| threads.get())
+                .unwrap_or(1),
+        };
+        let quiet = Self::get_required(matches, "quiet", config.quiet)?;
+        let output = Self::get_values(matches, "output", config.outputs);
+        let request_log = Self::get_value(matches, "request-log", config.request_log);
+        let save_responses = Self::get_value(matches, "save-responses", config.save_responses);
+        let raw_save_filter = Self::get_required(matches, "save-filter", config.save_filter)?;
+        let save_filter = CaptureFilter::parse(&raw_save_filter)?;
+        let mut assertions = Vec::new();
+
+        for raw_assertion in Self::get_values(matches, "assert", config.assertions) {
+            assertions.push(Assertion::parse(&raw_assertion)?);
+        }
+
+        let mut thresholds = Vec::new();
+
+        for raw_threshold in Self::get_values(matches, "threshold", config.thresholds) {
+            thresholds.push(Threshold::parse(&raw_threshold)?);
+        }
+
+        let scenario = match config.steps.is_empty() {
+            true => None,
+            false => Some(Scenario::parse(config.steps, &target, &method)?),
+        };
 
         Ok(Arguments {
             target,
             method,
             proxy,
             duration,
+            requests,
+            warmup,
+            rate,
             connections,
             payload,
             headers,
             timeout,
+            significant_figures,
+            engine,
+            threads,
+            profile,
+            quiet,
+            output,
+            request_log,
+            save_responses,
+            save_filter,
+            assertions,
+            thresholds,
+            scenario,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+    use crate::testing::temp_path;
+
+    fn config_file(name: &str, content: &str) -> PathBuf {
+        let path = temp_path(&format!("app-{}", name));
+
+        fs::write(&path, content).unwrap();
+
+        path
+    }
+
+    fn get_arguments(args: &[&str]) -> AppResult<Arguments> {
+        let command = App::get_command();
+        let matches = command
+            .clone()
+            .try_get_matches_from([&["cicadas"], args].concat())
+            .unwrap();
+
+        App { command, matches }.get_arguments()
+    }
+
+    fn run(name: &str, content: &str, args: &[&str]) -> AppResult<Arguments> {
+        let path = config_file(name, content);
+        let arguments = get_arguments(&[&["run", path.to_str().unwrap()], args].concat());
+
+        fs::remove_file(path).unwrap();
+
+        arguments
+    }
+
+    const CONFIG: &str = r#"
+target = "http://localhost:5500/"
+method = "post"
+duration = 10
+connections = 4
+body = "{}"
+outputs = ["report.json", "report.csv"]
+headers = { accept = "application/json", x-client = "config" }
+"#;
+
+    #[test]
+    fn reads_config_file_values() {
+        let arguments = run("values.toml", CONFIG, &[]).unwrap();
+
+        assert_eq!(arguments.target, "http://localhost:5500/");
+        assert_eq!(arguments.method, Method::POST);
+        assert_eq!(arguments.duration, Some(10));
+        assert_eq!(arguments.connections, 4);
+        assert_eq!(arguments.payload.as_deref(), Some("{}"));
+        // Relative paths are relative to config file.
+        assert_eq!(
+            arguments.output,
+            [
+                env::temp_dir().join("report.json"),
+                env::temp_dir().join("report.csv")
+            ]
+        );
+        // Command line defaults fill what config file leaves out.
+        assert_eq!(arguments.timeout, 5);
+        assert_eq!(arguments.warmup, 0);
+    }
+
+    #[test]
+    fn command_line_overrides_config_file() {
+        let arguments = run(
+            "override.toml",
+            CONFIG,
+            &["-d", "5", "-m", "get", "-o", "report.html", "-T", "2"],
+        )
+        .unwrap();
+
+        assert_eq!(arguments.duration, Some(5));
+        assert_eq!(arguments.method, Method::GET);
+        assert_eq!(arguments.output, [PathBuf::from("report.html")]);
+        assert_eq!(arguments.timeout, 2);
+        assert_eq!(arguments.connections, 4);
+    }
+
+    #[test]
+    fn merges_config_file_and_command_line_headers() {
+        let arguments = run("headers.toml", CONFIG, &["-H", "x-client:cli", "x-trace:1"]).unwrap();
+        let headers = arguments.headers.unwrap();
+
+        assert_eq!(headers.len(), 3);
+        assert_eq!(headers["accept"], "application/json");
+        assert_eq!(headers["x-client"], "cli");
+        assert_eq!(headers["x-trace"], "1");
+    }
+
+    #[test]
+    fn command_line_duration_replaces_config_file_profile() {
+        let config = "target: http://localhost:5500/\nmethod: GET\nprofile: [\"hold:30:8\"]\n";
+
+        let arguments = run("profile.yaml", config, &[]).unwrap();
+
+        assert!(arguments.profile.is_some());
+        assert_eq!(arguments.duration, Some(30));
+        assert_eq!(arguments.connections, 8);
+
+        let arguments = run("profile.yml", config, &["-d", "5", "-c", "2"]).unwrap();
+
+        assert!(arguments.profile.is_none());
+        assert_eq!(arguments.duration, Some(5));
+        assert_eq!(arguments.connections, 2);
+    }
+
+    #[test]
+    fn requires_values_from_config_file_or_command_line() {
+        assert!(matches!(
+            run(
+                "missing.toml",
+                "method = \"GET\"\nduration = 1\nconnections = 1",
+                &[]
+            ),
+            Err(ErrorType::InvalidConfig(_))
+        ));
+        assert!(run(
+            "missing.toml",
+            "method = \"GET\"\nduration = 1\nconnections = 1",
+            &["-t", "http://localhost:5500/"]
+        )
+        .is_ok());
+        assert!(matches!(
+            run(
+                "no-duration.toml",
+                "target = \"http://localhost/\"\nmethod = \"GET\"\nconnections = 1",
+                &[]
+            ),
+            Err(ErrorType::InvalidConfig(_))
+        ));
+    }
+}
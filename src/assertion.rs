@@ -0,0 +1,231 @@
+use std::time::Duration;
+
+use regex::bytes::Regex;
+use reqwest::header::HeaderName;
+use serde_json::Value;
+
+use crate::{
+    errors::{AppResult, ErrorType},
+    http::HttpResponse,
+};
+
+// A rule every response has to pass to be counted by its status code,
+// responses failing any of them are counted as assertion failed instead.
+#[derive(Debug, Clone)]
+pub enum Assertion {
+    Status(Vec<u16>),
+    BodyContains(String),
+    BodyRegex(Regex),
+    Json(String, Value),
+    Header(HeaderName),
+    MaxLatency(Duration),
+}
+
+impl Assertion {
+    pub fn parse(assertion: &str) -> AppResult<Self> {
+        let (kind, value) = match assertion.split_once(':') {
+            Some((kind, value)) => (kind.trim().to_lowercase(), value),
+            None => return Err(ErrorType::InvalidAssertion),
+        };
+
+        match kind.as_str() {
+            "status" => {
+                let mut status_codes = Vec::new();
+
+                for status_code in value.split(',') {
+                    match status_code.trim().parse() {
+                        Ok(status_code) => status_codes.push(status_code),
+                        Err(_) => return Err(ErrorType::InvalidAssertion),
+                    }
+                }
+
+                Ok(Self::Status(status_codes))
+            }
+            "body-contains" => Ok(Self::BodyContains(value.to_owned())),
+            "body-regex" => match Regex::new(value) {
+                Ok(regex) => Ok(Self::BodyRegex(regex)),
+                Err(_) => Err(ErrorType::InvalidAssertion),
+            },
+            "json" => {
+                let (path, expected) = match value.split_once('=') {
+                    Some((path, expected)) => (path.trim(), expected.trim()),
+                    None => return Err(ErrorType::InvalidAssertion),
+                };
+                // Values that aren't valid JSON, like unquoted text, are
+                // compared as strings.
+                let expected = serde_json::from_str(expected)
+                    .unwrap_or_else(|_| Value::String(expected.to_owned()));
+
+                Ok(Self::Json(Self::to_json_pointer(path), expected))
+            }
+            "header" => match value.trim().parse() {
+                Ok(name) => Ok(Self::Header(name)),
+                Err(_) => Err(ErrorType::InvalidAssertion),
+            },
+            "max-latency" => match value.trim().parse() {
+                Ok(millis) => Ok(Self::MaxLatency(Duration::from_millis(millis))),
+                Err(_) => Err(ErrorType::InvalidAssertion),
+            },
+            _ => Err(ErrorType::InvalidAssertion),
+        }
+    }
+
+    // Turns a dotted path like `$.items.0.id` into a JSON pointer, numeric
+    // segments index into arrays.
+    pub fn to_json_pointer(path: &str) -> String {
+        let path = path.trim_start_matches('$').trim_start_matches('.');
+
+        if path.is_empty() {
+            return String::new();
+        }
+
+        path.split('.')
+            .map(|segment| format!("/{}", segment.replace('~', "~0").replace('/', "~1")))
+            .collect()
+    }
+
+    pub fn needs_body(&self) -> bool {
+        matches!(
+            self,
+            Self::BodyContains(_) | Self::BodyRegex(_) | Self::Json(_, _)
+        )
+    }
+
+    pub fn check(&self, response: &HttpResponse, response_time: Duration) -> bool {
+        let body = response.body.as_deref().unwrap_or_default();
+
+        match self {
+            Self::Status(status_codes) => status_codes.contains(&response.status.as_u16()),
+            Self::BodyContains(text) => {
+                text.is_empty()
+                    || body
+                        .windows(text.len())
+                        .any(|window| window == text.as_bytes())
+            }
+            Self::BodyRegex(regex) => regex.is_match(body),
+            Self::Json(pointer, expected) => match serde_json::from_slice::<Value>(body) {
+                Ok(json) => json.pointer(pointer) == Some(expected),
+                Err(_) => false,
+            },
+            Self::Header(name) => response.headers.contains_key(name),
+            Self::MaxLatency(max_latency) => response_time <= *max_latency,
+        }
+    }
+
+    pub fn check_all(
+        assertions: &[Self],
+        response: &HttpResponse,
+        response_time: Duration,
+    ) -> bool {
+        assertions
+            .iter()
+            .all(|assertion| assertion.check(response, response_time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{header::HeaderMap, StatusCode};
+
+    use super::*;
+
+    fn parse(assertion: &str) -> Assertion {
+        Assertion::parse(assertion).unwrap()
+    }
+
+    fn response(status: u16, body: &str) -> HttpResponse {
+        let mut headers = HeaderMap::new();
+
+        headers.insert("x-request-id", "42".parse().unwrap());
+
+        HttpResponse {
+            status: StatusCode::from_u16(status).unwrap(),
+            headers,
+            body_size: body.len() as u64,
+            body: Some(body.as_bytes().to_vec()),
+        }
+    }
+
+    #[test]
+    fn parses_status_lists() {
+        assert!(matches!(parse("status:200"), Assertion::Status(codes) if codes == [200]));
+        assert!(
+            matches!(parse("Status: 200, 201 ,204"), Assertion::Status(codes) if codes == [200, 201, 204])
+        );
+    }
+
+    #[test]
+    fn parses_json_paths_into_pointers() {
+        assert!(
+            matches!(parse("json:$.items.0.id=42"), Assertion::Json(pointer, expected) if pointer == "/items/0/id" && expected == 42)
+        );
+        assert!(
+            matches!(parse("json:$.name = ok"), Assertion::Json(pointer, expected) if pointer == "/name" && expected == "ok")
+        );
+        assert_eq!(Assertion::to_json_pointer("$"), "");
+        assert_eq!(Assertion::to_json_pointer("a/b.c~d"), "/a~1b/c~0d");
+    }
+
+    #[test]
+    fn parses_headers_and_max_latency() {
+        assert!(
+            matches!(parse("header:X-Request-Id"), Assertion::Header(name) if name == "x-request-id")
+        );
+        assert!(
+            matches!(parse("max-latency:250"), Assertion::MaxLatency(max_latency) if max_latency == Duration::from_millis(250))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_assertions() {
+        assert!(Assertion::parse("status").is_err());
+        assert!(Assertion::parse("status:2xx").is_err());
+        assert!(Assertion::parse("json:$.id").is_err());
+        assert!(Assertion::parse("header:bad header").is_err());
+        assert!(Assertion::parse("max-latency:1s").is_err());
+        assert!(Assertion::parse("body-regex:(").is_err());
+        assert!(Assertion::parse("unknown:1").is_err());
+    }
+
+    #[test]
+    fn checks_responses() {
+        let response = response(200, r#"{"items":[{"id":42}],"name":"ok"}"#);
+        let response_time = Duration::from_millis(100);
+        let check = |assertion| parse(assertion).check(&response, response_time);
+
+        assert!(check("status:200,201"));
+        assert!(!check("status:201"));
+        assert!(check(r#"body-contains:"name""#));
+        assert!(!check("body-contains:missing"));
+        assert!(check(r#"body-regex:"id":\d+"#));
+        assert!(check("json:$.items.0.id=42"));
+        assert!(check("json:$.name=ok"));
+        assert!(!check("json:$.items.0.id=\"42\""));
+        assert!(check("header:x-request-id"));
+        assert!(!check("header:location"));
+        assert!(check("max-latency:100"));
+        assert!(!check("max-latency:99"));
+    }
+
+    #[test]
+    fn checks_all_assertions() {
+        let response = response(500, "");
+        let assertions = [parse("header:x-request-id"), parse("status:200")];
+
+        assert!(!Assertion::check_all(
+            &assertions,
+            &response,
+            Duration::ZERO
+        ));
+        assert!(Assertion::check_all(
+            &assertions[..1],
+            &response,
+            Duration::ZERO
+        ));
+        assert!(!Assertion::check(
+            &parse("json:$.id=1"),
+            &response,
+            Duration::ZERO
+        ));
+    }
+}
@@ -0,0 +1,335 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::Receiver,
+        Arc,
+    },
+};
+
+use reqwest::{header::HeaderMap, StatusCode};
+
+use crate::{
+    errors::{AppResult, ErrorType},
+    writer::{BackgroundSender, BackgroundWriter},
+};
+
+// Responses waiting to be written before connections block on sending, kept
+// small since every one of them holds a whole body.
+const CHANNEL_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Copy)]
+pub enum CaptureFilter {
+    All,
+    Non2xx,
+    Every(u64),
+}
+
+impl CaptureFilter {
+    pub fn parse(filter: &str) -> AppResult<Self> {
+        match filter.trim().to_lowercase().as_str() {
+            "all" => Ok(Self::All),
+            "non-2xx" => Ok(Self::Non2xx),
+            filter => match filter.strip_prefix("every:").map(str::parse) {
+                Some(Ok(every)) if every != 0 => Ok(Self::Every(every)),
+                _ => Err(ErrorType::InvalidSaveFilter),
+            },
+        }
+    }
+}
+
+struct CapturedResponse {
+    worker: usize,
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Vec<u8>,
+}
+
+// Saves responses picked by the filter into a single file, or one file per
+// response in a new `run-<N>` subdirectory when the path is a directory, so
+// runs never collide. Connections only push responses into a channel, disk
+// writes happen on a background thread.
+pub struct ResponseCapture {
+    filter: CaptureFilter,
+    responses_count: Arc<AtomicU64>,
+    writer: BackgroundWriter<CapturedResponse>,
+}
+
+impl ResponseCapture {
+    pub fn new(path: PathBuf, filter: CaptureFilter) -> AppResult<Self> {
+        let (path, writer) = if path.is_dir() {
+            (Self::create_run_dir(&path)?, None)
+        } else {
+            match File::create(&path) {
+                Ok(file) => (path, Some(BufWriter::new(file))),
+                Err(_) => return Err(ErrorType::ResponseSaveFailed),
+            }
+        };
+
+        Ok(Self {
+            filter,
+            responses_count: Arc::new(AtomicU64::new(0)),
+            writer: BackgroundWriter::spawn(CHANNEL_CAPACITY, move |receiver| {
+                Self::write(path, writer, receiver)
+            }),
+        })
+    }
+
+    pub fn get_sender(&self) -> AppResult<ResponseCaptureSender> {
+        Ok(ResponseCaptureSender {
+            filter: self.filter,
+            responses_count: self.responses_count.clone(),
+            sender: self.writer.get_sender()?,
+        })
+    }
+
+    // Waits for every pending response to be written.
+    pub fn finish(&self) -> AppResult<()> {
+        self.writer.finish()
+    }
+
+    // Created before load test starts, so an unwritable directory fails right
+    // away instead of after the whole run.
+    fn create_run_dir(path: &Path) -> AppResult<PathBuf> {
+        for run in 1.. {
+            let run_path = path.join(format!("run-{}", run));
+
+            match fs::create_dir(&run_path) {
+                Ok(_) => return Ok(run_path),
+                Err(error) if error.kind() == ErrorKind::AlreadyExists => continue,
+                Err(_) => return Err(ErrorType::ResponseSaveFailed),
+            }
+        }
+
+        Err(ErrorType::ResponseSaveFailed)
+    }
+
+    fn write(
+        path: PathBuf,
+        mut writer: Option<BufWriter<File>>,
+        receiver: Receiver<CapturedResponse>,
+    ) -> AppResult<()> {
+        for (index, response) in receiver.into_iter().enumerate() {
+            match writer.as_mut() {
+                Some(writer) => Self::write_response(writer, index, &response)?,
+                None => {
+                    let file =
+                        match OpenOptions::new().create_new(true).write(true).open(
+                            path.join(format!("{}-{}.txt", index + 1, response.status.as_u16())),
+                        ) {
+                            Ok(file) => file,
+                            Err(_) => return Err(ErrorType::ResponseSaveFailed),
+                        };
+                    let mut writer = BufWriter::new(file);
+
+                    Self::write_response(&mut writer, index, &response)?;
+                    Self::flush(&mut writer)?;
+                }
+            }
+        }
+
+        match writer.as_mut() {
+            Some(writer) => Self::flush(writer),
+            None => Ok(()),
+        }
+    }
+
+    fn write_response(
+        writer: &mut BufWriter<File>,
+        index: usize,
+        response: &CapturedResponse,
+    ) -> AppResult<()> {
+        let mut head = format!(
+            "### Response {} from connection {}\nHTTP {}\n",
+            index + 1,
+            response.worker,
+            response.status
+        );
+
+        for (name, value) in response.headers.iter() {
+            head.push_str(&format!(
+                "{}: {}\n",
+                name,
+                String::from_utf8_lossy(value.as_bytes())
+            ));
+        }
+
+        head.push('\n');
+
+        let result = writer
+            .write_all(head.as_bytes())
+            .and_then(|_| writer.write_all(&response.body))
+            .and_then(|_| writer.write_all(b"\n\n"));
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(_) => Err(ErrorType::ResponseSaveFailed),
+        }
+    }
+
+    fn flush(writer: &mut BufWriter<File>) -> AppResult<()> {
+        match writer.flush() {
+            Ok(_) => Ok(()),
+            Err(_) => Err(ErrorType::ResponseSaveFailed),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ResponseCaptureSender {
+    filter: CaptureFilter,
+    responses_count: Arc<AtomicU64>,
+    sender: BackgroundSender<CapturedResponse>,
+}
+
+impl ResponseCaptureSender {
+    // Sampling counts responses across all connections, so `every:<N>` saves
+    // one in N responses of the whole load test.
+    pub fn is_selected(&self, status: StatusCode) -> bool {
+        match self.filter {
+            CaptureFilter::All => true,
+            CaptureFilter::Non2xx => !status.is_success(),
+            CaptureFilter::Every(every) => self
+                .responses_count
+                .fetch_add(1, Ordering::Relaxed)
+                .is_multiple_of(every),
+        }
+    }
+
+    pub fn add(
+        &self,
+        worker: usize,
+        status: StatusCode,
+        headers: HeaderMap,
+        body: Vec<u8>,
+    ) -> AppResult<()> {
+        let response = CapturedResponse {
+            worker,
+            status,
+            headers,
+            body,
+        };
+
+        if !self.sender.send(response) {
+            return Err(ErrorType::ResponseSaveFailed);
+        }
+
+        Ok(())
+    }
+
+    pub async fn add_async(
+        &self,
+        worker: usize,
+        status: StatusCode,
+        headers: HeaderMap,
+        body: Vec<u8>,
+    ) -> AppResult<()> {
+        let response = CapturedResponse {
+            worker,
+            status,
+            headers,
+            body,
+        };
+
+        if !self.sender.send_async(response).await {
+            return Err(ErrorType::ResponseSaveFailed);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_path;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let path = temp_path(name);
+
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+
+        path
+    }
+
+    fn get_selected(filter: &str, statuses: &[u16]) -> Vec<bool> {
+        let path = temp_dir(&format!("filter-{}", filter.replace(':', "-")));
+        let capture = ResponseCapture::new(
+            path.join("responses.txt"),
+            CaptureFilter::parse(filter).unwrap(),
+        )
+        .unwrap();
+        let senders = [capture.get_sender().unwrap(), capture.get_sender().unwrap()];
+        let selected = statuses
+            .iter()
+            .enumerate()
+            .map(|(index, status)| {
+                senders[index % senders.len()].is_selected(StatusCode::from_u16(*status).unwrap())
+            })
+            .collect();
+
+        drop(senders);
+        capture.finish().unwrap();
+        fs::remove_dir_all(path).unwrap();
+
+        selected
+    }
+
+    #[test]
+    fn parses_filters() {
+        assert!(matches!(
+            CaptureFilter::parse("all"),
+            Ok(CaptureFilter::All)
+        ));
+        assert!(matches!(
+            CaptureFilter::parse(" Non-2xx "),
+            Ok(CaptureFilter::Non2xx)
+        ));
+        assert!(matches!(
+            CaptureFilter::parse("every:10"),
+            Ok(CaptureFilter::Every(10))
+        ));
+        assert!(CaptureFilter::parse("every:0").is_err());
+        assert!(CaptureFilter::parse("every:").is_err());
+        assert!(CaptureFilter::parse("every:-1").is_err());
+        assert!(CaptureFilter::parse("2xx").is_err());
+    }
+
+    #[test]
+    fn selects_responses_by_filter() {
+        assert_eq!(get_selected("all", &[200, 500]), [true, true]);
+        assert_eq!(
+            get_selected("non-2xx", &[200, 204, 302, 404, 500]),
+            [false, false, true, true, true]
+        );
+        // Sampling is shared by connections, so one in three responses is
+        // picked whichever connection got it.
+        assert_eq!(
+            get_selected("every:3", &[200, 200, 200, 200, 200, 200, 200]),
+            [true, false, false, true, false, false, true]
+        );
+    }
+
+    #[test]
+    fn numbers_run_dirs_skipping_existing_ones() {
+        let path = temp_dir("runs");
+
+        fs::create_dir(path.join("run-2")).unwrap();
+
+        assert_eq!(
+            ResponseCapture::create_run_dir(&path).unwrap(),
+            path.join("run-1")
+        );
+        assert_eq!(
+            ResponseCapture::create_run_dir(&path).unwrap(),
+            path.join("run-3")
+        );
+        assert!(path.join("run-3").is_dir());
+        assert!(ResponseCapture::create_run_dir(&path.join("missing")).is_err());
+
+        fs::remove_dir_all(path).unwrap();
+    }
+}
@@ -0,0 +1,284 @@
+use std::fmt::Write;
+
+const WIDTH: f64 = 800.0;
+const HEIGHT: f64 = 300.0;
+const MARGIN_LEFT: f64 = 70.0;
+const MARGIN_RIGHT: f64 = 20.0;
+const MARGIN_TOP: f64 = 20.0;
+const MARGIN_BOTTOM: f64 = 50.0;
+const GRID_LINES: usize = 5;
+const MAX_X_LABELS: usize = 10;
+
+pub struct Series<'a> {
+    pub label: &'a str,
+    pub color: &'a str,
+    pub values: Vec<f64>,
+}
+
+// Renders charts as inline SVG so reports can be viewed without loading any
+// script or stylesheet.
+pub struct Chart;
+
+impl Chart {
+    pub fn line(x_labels: &[String], y_label: &str, series: &[Series]) -> String {
+        let max_value = Self::get_max_value(series.iter().flat_map(|series| series.values.iter()));
+        let mut svg = Self::open(x_labels, y_label, max_value);
+        let slot = Self::get_slot_width(x_labels);
+
+        for series in series {
+            let points: Vec<String> = series
+                .values
+                .iter()
+                .enumerate()
+                .map(|(index, value)| {
+                    format!(
+                        "{:.1},{:.1}",
+                        MARGIN_LEFT + index as f64 * slot + slot / 2.0,
+                        Self::get_y(*value, max_value)
+                    )
+                })
+                .collect();
+
+            let _ = write!(
+                svg,
+                r#"<polyline fill="none" stroke="{}" stroke-width="2" points="{}"/>"#,
+                series.color,
+                points.join(" ")
+            );
+        }
+
+        Self::close(svg, series)
+    }
+
+    pub fn bar(x_labels: &[String], y_label: &str, series: &Series) -> String {
+        let max_value = Self::get_max_value(series.values.iter());
+        let mut svg = Self::open(x_labels, y_label, max_value);
+        let slot = Self::get_slot_width(x_labels);
+
+        for (index, value) in series.values.iter().enumerate() {
+            let y = Self::get_y(*value, max_value);
+
+            let _ = write!(
+                svg,
+                r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="{}"><title>{}: {}</title></rect>"#,
+                MARGIN_LEFT + index as f64 * slot + slot * 0.1,
+                y,
+                slot * 0.8,
+                HEIGHT - MARGIN_BOTTOM - y,
+                series.color,
+                Self::escape(&x_labels[index]),
+                value
+            );
+        }
+
+        Self::close(svg, std::slice::from_ref(series))
+    }
+
+    pub fn escape(text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+    }
+
+    fn open(x_labels: &[String], y_label: &str, max_value: f64) -> String {
+        let mut svg = format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {} {}" width="{}" height="{}" font-family="sans-serif" font-size="11">"#,
+            WIDTH, HEIGHT, WIDTH, HEIGHT
+        );
+
+        for line in 0..=GRID_LINES {
+            let value = max_value * line as f64 / GRID_LINES as f64;
+            let y = Self::get_y(value, max_value);
+
+            let _ = write!(
+                svg,
+                r##"<line x1="{}" y1="{:.1}" x2="{}" y2="{:.1}" stroke="#ddd"/><text x="{}" y="{:.1}" text-anchor="end">{}</text>"##,
+                MARGIN_LEFT,
+                y,
+                WIDTH - MARGIN_RIGHT,
+                y,
+                MARGIN_LEFT - 5.0,
+                y + 4.0,
+                Self::format_value(value)
+            );
+        }
+
+        let label_every = x_labels.len().div_ceil(MAX_X_LABELS).max(1);
+        let slot = Self::get_slot_width(x_labels);
+
+        for (index, label) in x_labels.iter().enumerate().step_by(label_every) {
+            let _ = write!(
+                svg,
+                r#"<text x="{:.1}" y="{}" text-anchor="middle">{}</text>"#,
+                MARGIN_LEFT + index as f64 * slot + slot / 2.0,
+                HEIGHT - MARGIN_BOTTOM + 15.0,
+                Self::escape(label)
+            );
+        }
+
+        let _ = write!(
+            svg,
+            r#"<text x="15" y="{:.1}" transform="rotate(-90 15 {:.1})" text-anchor="middle">{}</text>"#,
+            HEIGHT / 2.0,
+            HEIGHT / 2.0,
+            Self::escape(y_label)
+        );
+
+        svg
+    }
+
+    fn close(mut svg: String, series: &[Series]) -> String {
+        let mut x = MARGIN_LEFT;
+
+        for series in series {
+            let _ = write!(
+                svg,
+                r#"<rect x="{:.1}" y="{}" width="10" height="10" fill="{}"/><text x="{:.1}" y="{}">{}</text>"#,
+                x,
+                HEIGHT - 18.0,
+                series.color,
+                x + 14.0,
+                HEIGHT - 9.0,
+                Self::escape(series.label)
+            );
+
+            x += 24.0 + series.label.len() as f64 * 7.0;
+        }
+
+        svg.push_str("</svg>");
+        svg
+    }
+
+    fn get_max_value<'a, I>(values: I) -> f64
+    where
+        I: Iterator<Item = &'a f64>,
+    {
+        let max_value = values.fold(0.0, |max: f64, value| max.max(*value));
+
+        if max_value == 0.0 {
+            1.0
+        } else {
+            max_value
+        }
+    }
+
+    // Every x label gets an equal slot of plot width, points and bars are
+    // drawn at the middle of their slot.
+    fn get_slot_width(x_labels: &[String]) -> f64 {
+        (WIDTH - MARGIN_LEFT - MARGIN_RIGHT) / x_labels.len().max(1) as f64
+    }
+
+    fn get_y(value: f64, max_value: f64) -> f64 {
+        HEIGHT - MARGIN_BOTTOM - (value / max_value) * (HEIGHT - MARGIN_TOP - MARGIN_BOTTOM)
+    }
+
+    fn format_value(value: f64) -> String {
+        if value >= 100.0 {
+            format!("{:.0}", value)
+        } else {
+            format!("{:.2}", value)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(count: usize) -> Vec<String> {
+        (0..count).map(|index| format!("{}s", index)).collect()
+    }
+
+    #[test]
+    fn max_value_falls_back_to_one() {
+        assert_eq!(Chart::get_max_value([0.0, 0.0].iter()), 1.0);
+        assert_eq!(Chart::get_max_value([].iter()), 1.0);
+        assert_eq!(Chart::get_max_value([2.5, 7.0, 1.0].iter()), 7.0);
+    }
+
+    #[test]
+    fn renders_empty_series() {
+        let line = Chart::line(
+            &[],
+            "Requests",
+            &[Series {
+                label: "Requests",
+                color: "#22c55e",
+                values: Vec::new(),
+            }],
+        );
+        let bar = Chart::bar(
+            &[],
+            "Requests",
+            &Series {
+                label: "Requests",
+                color: "#3b82f6",
+                values: Vec::new(),
+            },
+        );
+
+        assert!(line.starts_with("<svg") && line.ends_with("</svg>"));
+        assert!(line.contains(r#"points="""#));
+        assert!(!line.contains("NaN"));
+        assert!(bar.starts_with("<svg") && bar.ends_with("</svg>"));
+        assert!(!bar.contains("<title>"));
+        assert!(!bar.contains("NaN"));
+    }
+
+    #[test]
+    fn zero_values_lie_on_the_axis() {
+        let line = Chart::line(
+            &labels(2),
+            "Requests",
+            &[Series {
+                label: "Errors",
+                color: "#ef4444",
+                values: vec![0.0, 0.0],
+            }],
+        );
+
+        // Slots of 355 px, points at their middle on the bottom of the plot.
+        assert!(line.contains(r#"points="247.5,250.0 602.5,250.0""#));
+        assert!(!line.contains("NaN"));
+    }
+
+    #[test]
+    fn scales_bars_to_max_value() {
+        let bar = Chart::bar(
+            &labels(2),
+            "Requests",
+            &Series {
+                label: "Requests",
+                color: "#3b82f6",
+                values: vec![5.0, 10.0],
+            },
+        );
+
+        assert!(bar.contains(r#"<rect x="105.5" y="135.0" width="284.0" height="115.0""#));
+        assert!(bar.contains(r#"<rect x="460.5" y="20.0" width="284.0" height="230.0""#));
+    }
+
+    #[test]
+    fn escapes_labels() {
+        let bar = Chart::bar(
+            &["<=5 ms".to_owned()],
+            "Time & \"size\"",
+            &Series {
+                label: "<b>",
+                color: "#3b82f6",
+                values: vec![1.0],
+            },
+        );
+
+        assert!(bar.contains("&lt;=5 ms"));
+        assert!(bar.contains("Time &amp; &quot;size&quot;"));
+        assert!(bar.contains("&lt;b&gt;"));
+        assert!(!bar.contains("<b>"));
+        assert!(!bar.contains("<=5"));
+        assert_eq!(
+            Chart::escape(r#"<a href="x">&</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;"
+        );
+    }
+}
@@ -0,0 +1,315 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::errors::{AppResult, ErrorType};
+
+// Load test described in a TOML or YAML file, keys are named after long
+// command line options, repeatable ones under a plural key that also accepts
+// the option name. Every value is optional here since command line can
+// provide or override any of them.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub target: Option<String>,
+    pub method: Option<String>,
+    pub proxy: Option<String>,
+    pub duration: Option<u64>,
+    pub requests: Option<u64>,
+    pub warmup: Option<u64>,
+    pub rate: Option<u64>,
+    pub connections: Option<usize>,
+    #[serde(alias = "body")]
+    pub payload: Option<String>,
+    pub headers: BTreeMap<String, String>,
+    pub timeout: Option<u64>,
+    pub significant_figures: Option<u8>,
+    pub engine: Option<String>,
+    pub threads: Option<usize>,
+    pub profile: Option<Vec<String>>,
+    pub profile_file: Option<PathBuf>,
+    pub quiet: Option<bool>,
+    #[serde(alias = "output")]
+    pub outputs: Option<Vec<PathBuf>>,
+    pub request_log: Option<PathBuf>,
+    pub save_responses: Option<PathBuf>,
+    pub save_filter: Option<String>,
+    #[serde(alias = "assert")]
+    pub assertions: Option<Vec<String>>,
+    #[serde(alias = "threshold")]
+    pub thresholds: Option<Vec<String>>,
+    pub steps: Vec<StepConfig>,
+}
+
+// One request of a scenario, url, header values and body can refer to
+// variables extracted by earlier steps as `{{name}}`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct StepConfig {
+    pub name: Option<String>,
+    pub method: Option<String>,
+    pub url: Option<String>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(alias = "body")]
+    pub payload: Option<String>,
+    #[serde(default)]
+    pub extract: BTreeMap<String, String>,
+    #[serde(default, alias = "assert")]
+    pub assertions: Vec<String>,
+}
+
+impl Config {
+    pub fn from_file(path: &Path) -> AppResult<Self> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase());
+
+        if !matches!(extension.as_deref(), Some("toml" | "yaml" | "yml")) {
+            return Err(ErrorType::InvalidConfigFormat);
+        }
+
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(_) => return Err(ErrorType::ConfigFileReadFailed),
+        };
+
+        let mut config: Self = match extension.as_deref() {
+            Some("toml") => toml::from_str(&content).map_err(|error| error.to_string()),
+            _ => serde_yaml::from_str(&content).map_err(|error| error.to_string()),
+        }
+        .map_err(Self::invalid)?;
+
+        config.validate()?;
+
+        if let Some(directory) = path.parent() {
+            config.resolve_paths(directory);
+        }
+
+        Ok(config)
+    }
+
+    // Relative paths in a config file are relative to the file itself, so a
+    // load test runs the same from any working directory.
+    fn resolve_paths(&mut self, directory: &Path) {
+        for path in [
+            &mut self.profile_file,
+            &mut self.request_log,
+            &mut self.save_responses,
+        ]
+        .into_iter()
+        .flatten()
+        .chain(self.outputs.iter_mut().flatten())
+        {
+            if path.is_relative() {
+                *path = directory.join(&*path);
+            }
+        }
+    }
+
+    pub fn invalid(message: String) -> ErrorType {
+        ErrorType::InvalidConfig(format!("Invalid config file. {}", message.trim_end()))
+    }
+
+    // Mirrors checks command line parser does on the same options.
+    fn validate(&self) -> AppResult<()> {
+        if self.profile.is_some() && self.profile_file.is_some() {
+            return Err(Self::invalid(
+                "profile and profile-file can't be used together.".to_owned(),
+            ));
+        }
+        if (self.profile.is_some() || self.profile_file.is_some())
+            && (self.duration.is_some() || self.connections.is_some())
+        {
+            return Err(Self::invalid(
+                "duration and connections can't be used with a load profile.".to_owned(),
+            ));
+        }
+
+        for (key, value) in [
+            ("requests", self.requests),
+            ("rate", self.rate),
+            ("threads", self.threads.map(|threads| threads as u64)),
+        ] {
+            if value == Some(0) {
+                return Err(Self::invalid(format!("{} must be at least 1.", key)));
+            }
+        }
+
+        if let Some(significant_figures) = self.significant_figures {
+            if !(1..=5).contains(&significant_figures) {
+                return Err(ErrorType::InvalidSignificantFigures);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::testing::temp_path;
+
+    fn parse(name: &str, content: &str) -> AppResult<Config> {
+        let path = temp_path(&format!("config-{}", name));
+
+        fs::write(&path, content).unwrap();
+
+        let config = Config::from_file(&path);
+
+        fs::remove_file(path).unwrap();
+
+        config
+    }
+
+    fn is_invalid(config: AppResult<Config>) -> bool {
+        matches!(config, Err(ErrorType::InvalidConfig(_)))
+    }
+
+    #[test]
+    fn parses_toml_and_yaml() {
+        let toml = r#"
+target = "http://localhost:5500/"
+method = "GET"
+profile = ["ramp:10:50", "hold:30:50"]
+significant-figures = 4
+thresholds = ["p99<250ms"]
+headers = { accept = "application/json" }
+
+[[steps]]
+url = "/login"
+body = "{}"
+extract = { token = "json:$.token" }
+"#;
+        let yaml = r#"
+target: http://localhost:5500/
+method: GET
+profile: [ramp:10:50, hold:30:50]
+significant-figures: 4
+thresholds: [p99<250ms]
+headers:
+  accept: application/json
+steps:
+  - url: /login
+    body: "{}"
+    extract:
+      token: json:$.token
+"#;
+
+        for config in [parse("config.toml", toml), parse("config.yaml", yaml)] {
+            let config = config.unwrap();
+
+            assert_eq!(config.target.as_deref(), Some("http://localhost:5500/"));
+            assert_eq!(config.profile.unwrap(), ["ramp:10:50", "hold:30:50"]);
+            assert_eq!(config.significant_figures, Some(4));
+            assert_eq!(config.thresholds.unwrap(), ["p99<250ms"]);
+            assert_eq!(config.headers["accept"], "application/json");
+            assert_eq!(config.steps.len(), 1);
+            assert_eq!(config.steps[0].payload.as_deref(), Some("{}"));
+            assert_eq!(config.steps[0].extract["token"], "json:$.token");
+        }
+    }
+
+    #[test]
+    fn accepts_option_names_of_repeatable_keys() {
+        let config = parse(
+            "aliases.yaml",
+            r#"
+output: [/tmp/report.json]
+assert: [status:200]
+threshold: [p99<250ms]
+payload: "{}"
+steps:
+  - assert: [status:201]
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.outputs.unwrap(), [PathBuf::from("/tmp/report.json")]);
+        assert_eq!(config.assertions.unwrap(), ["status:200"]);
+        assert_eq!(config.thresholds.unwrap(), ["p99<250ms"]);
+        assert_eq!(config.payload.as_deref(), Some("{}"));
+        assert_eq!(config.steps[0].assertions, ["status:201"]);
+        assert!(is_invalid(parse(
+            "duplicates.toml",
+            "outputs = [\"a.json\"]\noutput = [\"b.json\"]"
+        )));
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_formats() {
+        assert!(is_invalid(parse(
+            "unknown.toml",
+            "target = \"x\"\nconnection = 1"
+        )));
+        assert!(is_invalid(parse("unknown.yml", "steps:\n  - path: /\n")));
+        assert!(is_invalid(parse("types.toml", "duration = \"ten\"")));
+        assert!(matches!(
+            parse("config.json", "{}"),
+            Err(ErrorType::InvalidConfigFormat)
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_combinations() {
+        assert!(is_invalid(parse(
+            "profiles.toml",
+            "profile = [\"hold:10:1\"]\nprofile-file = \"stages.txt\""
+        )));
+        assert!(is_invalid(parse(
+            "profile-duration.toml",
+            "profile = [\"hold:10:1\"]\nduration = 10"
+        )));
+        assert!(is_invalid(parse(
+            "profile-connections.yaml",
+            "profile-file: stages.txt\nconnections: 2"
+        )));
+        assert!(is_invalid(parse("requests.toml", "requests = 0")));
+        assert!(is_invalid(parse("rate.toml", "rate = 0")));
+        assert!(matches!(
+            parse("figures.toml", "significant-figures = 6"),
+            Err(ErrorType::InvalidSignificantFigures)
+        ));
+        assert!(parse("valid.toml", "duration = 10\nconnections = 2").is_ok());
+    }
+
+    #[test]
+    fn relative_paths_are_relative_to_config_file() {
+        let config = parse(
+            "paths.toml",
+            r#"
+profile-file = "profiles/ramp.txt"
+request-log = "/var/log/requests.jsonl"
+save-responses = "responses"
+outputs = ["report.json", "/tmp/report.csv"]
+"#,
+        )
+        .unwrap();
+        let directory = env::temp_dir();
+
+        assert_eq!(
+            config.profile_file.unwrap(),
+            directory.join("profiles/ramp.txt")
+        );
+        assert_eq!(
+            config.request_log.unwrap(),
+            PathBuf::from("/var/log/requests.jsonl")
+        );
+        assert_eq!(config.save_responses.unwrap(), directory.join("responses"));
+        assert_eq!(
+            config.outputs.unwrap(),
+            [
+                directory.join("report.json"),
+                PathBuf::from("/tmp/report.csv")
+            ]
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    sync::{
+        mpsc::{self, RecvTimeoutError, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    app::Arguments,
+    errors::{AppResult, ErrorType},
+    http::StatusCodeCategory,
+    scheduler::Scheduler,
+    storage::{ResponseTimes, Storage},
+};
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+// Live rate and percentiles cover this long, so they don't jump around with
+// which workers happened to flush in the last refresh interval.
+const LIVE_WINDOW: Duration = Duration::from_secs(10);
+
+// Response times of the refresh intervals still inside live window.
+struct LiveWindow {
+    start_time: Instant,
+    intervals: VecDeque<(Instant, ResponseTimes)>,
+}
+
+impl LiveWindow {
+    fn new(start_time: Instant) -> Self {
+        Self {
+            start_time,
+            intervals: VecDeque::new(),
+        }
+    }
+
+    fn push(&mut self, end_time: Instant, response_times: ResponseTimes) {
+        self.intervals.push_back((end_time, response_times));
+
+        while let Some((oldest_end_time, _)) = self.intervals.front() {
+            if end_time.duration_since(*oldest_end_time) < LIVE_WINDOW {
+                break;
+            }
+
+            self.start_time = *oldest_end_time;
+            self.intervals.pop_front();
+        }
+    }
+
+    fn get_response_times(&self, significant_figures: u8) -> AppResult<ResponseTimes> {
+        let response_times = ResponseTimes::new(significant_figures)?;
+
+        for (_, interval_response_times) in &self.intervals {
+            response_times.add(interval_response_times)?;
+        }
+
+        Ok(response_times)
+    }
+
+    fn get_duration(&self) -> Duration {
+        match self.intervals.back() {
+            Some((end_time, _)) => end_time.duration_since(self.start_time),
+            None => Duration::ZERO,
+        }
+    }
+}
+
+// Live progress shown while load test is running, redrawn in place once per
+// second from what workers have flushed into storage so far.
+pub struct Dashboard {
+    sender: Sender<()>,
+    handle: JoinHandle<AppResult<()>>,
+}
+
+impl Dashboard {
+    pub fn spawn(
+        arguments: Arc<Arguments>,
+        storage: Arc<Storage>,
+        scheduler: Arc<Scheduler>,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel();
+
+        let handle = thread::spawn(move || {
+            let mut drawn_lines = 0;
+            let mut live_window = LiveWindow::new(Instant::now());
+
+            while let Err(RecvTimeoutError::Timeout) = receiver.recv_timeout(REFRESH_INTERVAL) {
+                live_window.push(Instant::now(), storage.take_live_response_times()?);
+
+                let lines = Self::render(
+                    &arguments,
+                    &storage,
+                    &scheduler,
+                    &live_window.get_response_times(arguments.significant_figures)?,
+                    live_window.get_duration(),
+                )?;
+
+                Self::clear(drawn_lines);
+                for line in &lines {
+                    println!("{}", line);
+                }
+                Self::flush();
+
+                drawn_lines = lines.len();
+            }
+
+            Self::clear(drawn_lines);
+            Self::flush();
+
+            Ok(())
+        });
+
+        Self { sender, handle }
+    }
+
+    pub fn stop(self) -> AppResult<()> {
+        drop(self.sender);
+
+        match self.handle.join() {
+            Ok(result) => result,
+            Err(_) => Err(ErrorType::InvalidError),
+        }
+    }
+
+    fn render(
+        arguments: &Arguments,
+        storage: &Storage,
+        scheduler: &Scheduler,
+        live_response_times: &ResponseTimes,
+        live_duration: Duration,
+    ) -> AppResult<Vec<String>> {
+        let mut lines = Vec::with_capacity(3);
+        let warmup_remaining_time = scheduler.get_warmup_remaining_time();
+
+        if !warmup_remaining_time.is_zero() {
+            lines.push(format!(
+                "Warming up: {}s remaining",
+                warmup_remaining_time.as_secs()
+            ));
+        } else {
+            let elapsed_time = scheduler.get_elapsed_time().as_secs();
+            let mut progress = match arguments.duration {
+                Some(duration) => format!(
+                    "Elapsed: {}s / {}s ({}s remaining)",
+                    elapsed_time,
+                    duration,
+                    duration.saturating_sub(elapsed_time)
+                ),
+                None => format!("Elapsed: {}s", elapsed_time),
+            };
+
+            if let Some(requests) = arguments.requests {
+                progress.push_str(&format!(
+                    ", Requests: {} / {}",
+                    storage.get_response_times().get_total_requests_count()?,
+                    requests
+                ));
+            }
+
+            lines.push(progress);
+        }
+
+        let live_requests_count = live_response_times.get_total_requests_count()?;
+        let live_errors_count = live_response_times.get_errors_count()?;
+        let error_rate = if live_requests_count == 0 {
+            0.0
+        } else {
+            live_errors_count as f64 * 100.0 / live_requests_count as f64
+        };
+
+        lines.push(format!(
+            "Req/s: {:.2}, P50: {:.2} ms, P99: {:.2} ms, Errors: {:.2}% (last {}s)",
+            Storage::get_rate(live_requests_count as f64, live_duration.as_secs_f64()),
+            live_response_times.get_total_percentile_response_time(50.0)?,
+            live_response_times.get_total_percentile_response_time(99.0)?,
+            error_rate,
+            live_duration.as_secs_f64().round()
+        ));
+
+        let mut counts = Vec::with_capacity(StatusCodeCategory::all().len());
+
+        for status_code_category in StatusCodeCategory::all() {
+            counts.push(format!(
+                "{}: {}",
+                status_code_category.label(),
+                storage
+                    .get_response_times()
+                    .get_requests_count(status_code_category)?
+            ));
+        }
+
+        lines.push(counts.join(", "));
+
+        Ok(lines)
+    }
+
+    // Moves cursor back to first drawn line and erases everything below it.
+    fn clear(drawn_lines: usize) {
+        if drawn_lines != 0 {
+            print!("\x1b[{}A\x1b[J", drawn_lines);
+        }
+    }
+
+    fn flush() {
+        let _ = io::stdout().flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recorder::Recorder;
+
+    fn response_times(requests: usize) -> ResponseTimes {
+        let response_times = ResponseTimes::new(3).unwrap();
+        let mut histogram = Recorder::new_histogram(3).unwrap();
+
+        for _ in 0..requests {
+            histogram.record(1000).unwrap();
+        }
+
+        response_times
+            .merge([(StatusCodeCategory::Success, &histogram)].into_iter())
+            .unwrap();
+
+        response_times
+    }
+
+    #[test]
+    fn live_window_keeps_last_intervals() {
+        let start_time = Instant::now();
+        let mut live_window = LiveWindow::new(start_time);
+
+        for second in 1..=12 {
+            live_window.push(start_time + Duration::from_secs(second), response_times(1));
+        }
+
+        let response_times = live_window.get_response_times(3).unwrap();
+
+        assert_eq!(live_window.get_duration(), LIVE_WINDOW);
+        assert_eq!(response_times.get_total_requests_count().unwrap(), 10);
+        assert_eq!(
+            response_times
+                .get_total_percentile_response_time(50.0)
+                .unwrap(),
+            1.0
+        );
+    }
+}
@@ -0,0 +1,711 @@
+use std::{
+    cell::RefCell,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use tokio::{runtime::Builder, time};
+
+use crate::{
+    app::Arguments,
+    assertion::Assertion,
+    capture::ResponseCapture,
+    errors::{AppResult, ErrorType},
+    http::{
+        ConnectTimes, FailureKind, HttpClientAsync, HttpClientBlocking, HttpResponse, Phase,
+        StatusCodeCategory, PHASES,
+    },
+    pool::ThreadPool,
+    recorder::{Recorder, Sample},
+    request_log::RequestLog,
+    scenario::{Scenario, VirtualUser},
+    scheduler::{Scheduler, Slot},
+    storage::Storage,
+};
+
+// Longest time a connection sleeps without checking whether load test has
+// been stopped.
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+thread_local! {
+    static RECORDER: RefCell<Option<Recorder>> = const { RefCell::new(None) };
+}
+
+// Instants a request went through, response time, corrected response time
+// and phases are all derived from them.
+struct RequestTimes {
+    intended_time: Instant,
+    connect_times: ConnectTimes,
+    start_time: Instant,
+    first_byte_time: Instant,
+    end_time: Instant,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Engine {
+    Blocking,
+    Async,
+}
+
+impl Engine {
+    pub fn label(&self) -> &str {
+        match self {
+            Self::Blocking => "blocking",
+            Self::Async => "async",
+        }
+    }
+
+    pub fn run(
+        &self,
+        arguments: Arc<Arguments>,
+        storage: Arc<Storage>,
+        scheduler: Arc<Scheduler>,
+        request_log: Option<Arc<RequestLog>>,
+        capture: Option<Arc<ResponseCapture>>,
+    ) -> AppResult<()> {
+        match self {
+            Self::Blocking => Self::run_blocking(
+                arguments,
+                storage.clone(),
+                scheduler.clone(),
+                request_log,
+                capture,
+            )?,
+            Self::Async => Self::run_async(
+                arguments,
+                storage.clone(),
+                scheduler.clone(),
+                request_log,
+                capture,
+            )?,
+        }
+
+        storage.set_interrupted(scheduler.is_stopped());
+        storage.set_elapsed_time(scheduler.get_run_time())
+    }
+
+    fn run_blocking(
+        arguments: Arc<Arguments>,
+        storage: Arc<Storage>,
+        scheduler: Arc<Scheduler>,
+        request_log: Option<Arc<RequestLog>>,
+        capture: Option<Arc<ResponseCapture>>,
+    ) -> AppResult<()> {
+        let mut thread_pool = ThreadPool::new();
+
+        for worker in 0..arguments.connections {
+            let arguments = arguments.clone();
+            let storage = storage.clone();
+            let scheduler = scheduler.clone();
+            let request_log = request_log.clone();
+            let capture = capture.clone();
+
+            thread_pool.add(Box::new(move || {
+                let http_client = HttpClientBlocking::from_arguments(&arguments)?;
+                let mut recorder = Recorder::new(arguments.significant_figures);
+                let request_log = match request_log {
+                    Some(request_log) => Some(request_log.get_sender()?),
+                    None => None,
+                };
+                let capture = match capture {
+                    Some(capture) => Some(capture.get_sender()?),
+                    None => None,
+                };
+                let keep_body = Self::keeps_body(&arguments);
+                let mut virtual_user = arguments.scenario.as_ref().map(VirtualUser::new);
+
+                loop {
+                    let (intended_time, stage, warmup) = match scheduler.next(worker) {
+                        Slot::Send(intended_time, stage) => (intended_time, stage, false),
+                        Slot::WarmUp(intended_time) => (intended_time, None, true),
+                        Slot::Wait(activation_time) => {
+                            Self::sleep_until(&scheduler, activation_time);
+                            continue;
+                        }
+                        Slot::Finish => break,
+                    };
+
+                    Self::sleep_until(&scheduler, intended_time);
+
+                    if scheduler.is_stopped() {
+                        break;
+                    }
+
+                    let step_request = virtual_user.as_ref().map(VirtualUser::get_request);
+                    let start_time = Instant::now();
+                    let response = match &step_request {
+                        Some(step_request) => http_client.send(step_request),
+                        None => http_client.call(),
+                    };
+                    let first_byte_time = Instant::now();
+                    let connect_times = http_client.take_connect_times();
+
+                    // Bodies are read to the end and timed as download, but
+                    // only kept when assertions, extractors or saved responses need them.
+                    let response = match response {
+                        Ok(response) => {
+                            let captured = capture
+                                .as_ref()
+                                .is_some_and(|capture| capture.is_selected(response.status()));
+
+                            HttpResponse::from_blocking(response, captured || keep_body)
+                                .map(|response| (response, captured))
+                        }
+                        Err(error) => Err(error),
+                    };
+
+                    let request_times = RequestTimes {
+                        intended_time,
+                        connect_times,
+                        start_time,
+                        first_byte_time,
+                        end_time: Instant::now(),
+                    };
+                    let mut sample = Self::get_sample(
+                        &arguments,
+                        &response,
+                        &request_times,
+                        worker,
+                        stage,
+                        scheduler.get_elapsed_time().as_secs(),
+                        warmup,
+                    );
+
+                    if let (Some(virtual_user), Some(step_request)) =
+                        (&mut virtual_user, &step_request)
+                    {
+                        virtual_user.complete(
+                            step_request,
+                            response.as_ref().ok().map(|(response, _)| response),
+                            &mut sample,
+                        );
+                    }
+
+                    recorder.add_sample(&sample)?;
+
+                    if let Some(request_log) = &request_log {
+                        request_log.add(request_times.start_time, &sample)?;
+                    }
+                    if let (Some(capture), Ok((response, true))) = (&capture, response) {
+                        capture.add(
+                            worker,
+                            response.status,
+                            response.headers,
+                            response.body.unwrap_or_default(),
+                        )?;
+                    }
+
+                    if recorder.is_flush_due() {
+                        storage.merge(&recorder)?;
+                        recorder = Recorder::new(arguments.significant_figures);
+                    }
+                }
+
+                Ok(recorder)
+            }));
+        }
+
+        for recorder in ThreadPool::wait_execution(thread_pool)? {
+            storage.merge(&recorder)?;
+        }
+
+        Ok(())
+    }
+
+    // Connections are spawned as tasks over a few runtime threads. One HTTP
+    // client is built per runtime thread and handed to connections round-robin,
+    // tasks may still move between threads so a client's pool is shared by
+    // whichever threads its connections run on. Each runtime thread records
+    // into its own recorder so tasks never share a lock while recording, and
+    // recorders are collected when runtime threads stop.
+    fn run_async(
+        arguments: Arc<Arguments>,
+        storage: Arc<Storage>,
+        scheduler: Arc<Scheduler>,
+        request_log: Option<Arc<RequestLog>>,
+        capture: Option<Arc<ResponseCapture>>,
+    ) -> AppResult<()> {
+        let recorders = Arc::new(Mutex::new(Vec::with_capacity(arguments.threads)));
+        let stopped_recorders = recorders.clone();
+
+        let runtime = match Builder::new_multi_thread()
+            .worker_threads(arguments.threads)
+            .enable_all()
+            .on_thread_stop(move || {
+                if let Some(recorder) = RECORDER.with(|recorder| recorder.borrow_mut().take()) {
+                    if let Ok(mut recorders) = stopped_recorders.lock() {
+                        recorders.push(recorder);
+                    }
+                }
+            })
+            .build()
+        {
+            Ok(runtime) => runtime,
+            Err(_) => return Err(ErrorType::AsyncRuntimeBuildFailed),
+        };
+
+        let mut http_clients = Vec::with_capacity(arguments.threads);
+
+        for _ in 0..arguments.threads {
+            http_clients.push(HttpClientAsync::from_arguments(&arguments)?);
+        }
+
+        let result = runtime.block_on(async {
+            let mut tasks = Vec::with_capacity(arguments.connections);
+
+            for worker in 0..arguments.connections {
+                let arguments = arguments.clone();
+                let storage = storage.clone();
+                let scheduler = scheduler.clone();
+                let http_client = http_clients[worker % http_clients.len()].clone();
+                let request_log = match &request_log {
+                    Some(request_log) => Some(request_log.get_sender()?),
+                    None => None,
+                };
+                let capture = match &capture {
+                    Some(capture) => Some(capture.get_sender()?),
+                    None => None,
+                };
+                let keep_body = Self::keeps_body(&arguments);
+
+                tasks.push(tokio::spawn(async move {
+                    let mut virtual_user = arguments.scenario.as_ref().map(VirtualUser::new);
+
+                    loop {
+                        let (intended_time, stage, warmup) = match scheduler.next(worker) {
+                            Slot::Send(intended_time, stage) => (intended_time, stage, false),
+                            Slot::WarmUp(intended_time) => (intended_time, None, true),
+                            Slot::Wait(activation_time) => {
+                                Self::sleep_until_async(&scheduler, activation_time).await;
+                                continue;
+                            }
+                            Slot::Finish => break,
+                        };
+
+                        Self::sleep_until_async(&scheduler, intended_time).await;
+
+                        if scheduler.is_stopped() {
+                            break;
+                        }
+
+                        let step_request = virtual_user.as_ref().map(VirtualUser::get_request);
+                        let start_time = Instant::now();
+                        let (response, connect_times) = HttpClientAsync::time_connect(async {
+                            match &step_request {
+                                Some(step_request) => http_client.send(step_request).await,
+                                None => http_client.call().await,
+                            }
+                        })
+                        .await;
+                        let first_byte_time = Instant::now();
+
+                        let response = match response {
+                            Ok(response) => {
+                                let captured = capture
+                                    .as_ref()
+                                    .is_some_and(|capture| capture.is_selected(response.status()));
+
+                                HttpResponse::from_async(response, captured || keep_body)
+                                    .await
+                                    .map(|response| (response, captured))
+                            }
+                            Err(error) => Err(error),
+                        };
+
+                        let request_times = RequestTimes {
+                            intended_time,
+                            connect_times,
+                            start_time,
+                            first_byte_time,
+                            end_time: Instant::now(),
+                        };
+                        let mut sample = Self::get_sample(
+                            &arguments,
+                            &response,
+                            &request_times,
+                            worker,
+                            stage,
+                            scheduler.get_elapsed_time().as_secs(),
+                            warmup,
+                        );
+
+                        if let (Some(virtual_user), Some(step_request)) =
+                            (&mut virtual_user, &step_request)
+                        {
+                            virtual_user.complete(
+                                step_request,
+                                response.as_ref().ok().map(|(response, _)| response),
+                                &mut sample,
+                            );
+                        }
+
+                        Self::record(&storage, arguments.significant_figures, &sample)?;
+
+                        if let Some(request_log) = &request_log {
+                            request_log
+                                .add_async(request_times.start_time, &sample)
+                                .await?;
+                        }
+                        if let (Some(capture), Ok((response, true))) = (&capture, response) {
+                            capture
+                                .add_async(
+                                    worker,
+                                    response.status,
+                                    response.headers,
+                                    response.body.unwrap_or_default(),
+                                )
+                                .await?;
+                        }
+                    }
+
+                    Ok(())
+                }));
+            }
+
+            for task in tasks {
+                match task.await {
+                    Ok(result) => result?,
+                    Err(_) => return Err(ErrorType::InvalidError),
+                }
+            }
+
+            Ok(())
+        });
+
+        drop(runtime);
+        result?;
+
+        let recorders = match recorders.lock() {
+            Ok(recorders) => recorders,
+            Err(_) => return Err(ErrorType::InvalidError),
+        };
+
+        for recorder in recorders.iter() {
+            storage.merge(recorder)?;
+        }
+
+        Ok(())
+    }
+
+    // Bodies are kept in memory only when an assertion or a scenario extractor
+    // reads them.
+    fn keeps_body(arguments: &Arguments) -> bool {
+        arguments.assertions.iter().any(Assertion::needs_body)
+            || arguments
+                .scenario
+                .as_ref()
+                .is_some_and(Scenario::needs_body)
+    }
+
+    // Responses failing any assertion are counted apart from their status code
+    // category, corrected response time is only kept in rate mode and phases
+    // only for requests that got a response.
+    fn get_sample(
+        arguments: &Arguments,
+        response: &Result<(HttpResponse, bool), reqwest::Error>,
+        request_times: &RequestTimes,
+        worker: usize,
+        stage: Option<usize>,
+        second: u64,
+        warmup: bool,
+    ) -> Sample {
+        let response_time = request_times.end_time - request_times.start_time;
+        let mut phase_times = [None; PHASES];
+
+        // Phases don't overlap, time to first byte leaves out opening a new
+        // connection.
+        if response.is_ok() {
+            for phase in [Phase::Dns, Phase::Connect, Phase::Tls] {
+                phase_times[phase as usize] = request_times.connect_times.get_phase_time(phase);
+            }
+            phase_times[Phase::TimeToFirstByte as usize] = Some(
+                (request_times.first_byte_time - request_times.start_time)
+                    .saturating_sub(request_times.connect_times.get_total_time()),
+            );
+            phase_times[Phase::Download as usize] =
+                Some(request_times.end_time - request_times.first_byte_time);
+        }
+
+        let (status_code_category, status_code, received_bytes, failure_kind, failure_message) =
+            match response {
+                Ok((response, _)) => (
+                    match Assertion::check_all(&arguments.assertions, response, response_time) {
+                        true => response.status.into(),
+                        false => StatusCodeCategory::AssertionFailed,
+                    },
+                    Some(response.status.as_u16()),
+                    Some(response.body_size),
+                    None,
+                    None,
+                ),
+                Err(error) => (
+                    StatusCodeCategory::Failed,
+                    None,
+                    None,
+                    Some(FailureKind::from(error)),
+                    Some(FailureKind::get_message(error)),
+                ),
+            };
+
+        // Only request body is counted as sent, headers are added by HTTP
+        // client and their size on the wire isn't known.
+        let sent_bytes = response.as_ref().ok().map(|_| {
+            arguments
+                .payload
+                .as_ref()
+                .map_or(0, |payload| payload.len() as u64)
+        });
+
+        Sample {
+            worker,
+            status_code_category,
+            status_code,
+            failure_kind,
+            failure_message,
+            sent_bytes,
+            received_bytes,
+            response_time,
+            corrected_response_time: arguments
+                .rate
+                .map(|_| request_times.end_time - request_times.intended_time),
+            phase_times,
+            stage,
+            step: None,
+            second,
+            warmup,
+        }
+    }
+
+    fn record(storage: &Storage, significant_figures: u8, sample: &Sample) -> AppResult<()> {
+        RECORDER.with(|recorder| {
+            let mut recorder = recorder.borrow_mut();
+            let recorder = recorder.get_or_insert_with(|| Recorder::new(significant_figures));
+
+            recorder.add_sample(sample)?;
+
+            if recorder.is_flush_due() {
+                storage.merge(recorder)?;
+                *recorder = Recorder::new(significant_figures);
+            }
+
+            Ok(())
+        })
+    }
+
+    fn sleep_until(scheduler: &Scheduler, time: Instant) {
+        loop {
+            let now = Instant::now();
+
+            if time <= now || scheduler.is_stopped() {
+                return;
+            }
+
+            thread::sleep((time - now).min(STOP_CHECK_INTERVAL));
+        }
+    }
+
+    async fn sleep_until_async(scheduler: &Scheduler, time: Instant) {
+        loop {
+            let now = Instant::now();
+
+            if time <= now || scheduler.is_stopped() {
+                return;
+            }
+
+            time::sleep((time - now).min(STOP_CHECK_INTERVAL)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{header::HeaderMap, Method, StatusCode};
+
+    use super::*;
+
+    fn millis(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn response() -> Result<(HttpResponse, bool), reqwest::Error> {
+        response_with_status(StatusCode::OK)
+    }
+
+    fn response_with_status(status: StatusCode) -> Result<(HttpResponse, bool), reqwest::Error> {
+        Ok((
+            HttpResponse {
+                status,
+                headers: HeaderMap::new(),
+                body_size: 2,
+                body: None,
+            },
+            false,
+        ))
+    }
+
+    // Request taking 100 ms whose response headers arrived after 70 ms,
+    // opening a new connection first when given how long that took.
+    fn request_times(connect_time: Option<Duration>) -> RequestTimes {
+        let start_time = Instant::now();
+
+        RequestTimes {
+            intended_time: start_time,
+            connect_times: connect_time.map_or_else(ConnectTimes::default, |connect_time| {
+                ConnectTimes::from_instants(start_time, start_time + connect_time)
+            }),
+            start_time,
+            first_byte_time: start_time + millis(70),
+            end_time: start_time + millis(100),
+        }
+    }
+
+    #[test]
+    fn time_to_first_byte_leaves_out_opening_connection() {
+        let arguments = Arguments::new("http://localhost:5500/".to_owned(), Method::GET);
+        let request_times = request_times(Some(millis(25)));
+
+        let sample = Engine::get_sample(&arguments, &response(), &request_times, 0, None, 0, false);
+
+        assert_eq!(
+            sample.phase_times[Phase::TimeToFirstByte as usize],
+            Some(millis(45))
+        );
+        assert_eq!(
+            sample.phase_times[Phase::Download as usize],
+            Some(millis(30))
+        );
+        assert_eq!(
+            sample.phase_times[Phase::Connect as usize],
+            Some(millis(25))
+        );
+        assert_eq!(sample.response_time, millis(100));
+    }
+
+    #[test]
+    fn pooled_connection_is_all_time_to_first_byte() {
+        let arguments = Arguments::new("http://localhost:5500/".to_owned(), Method::GET);
+        let request_times = request_times(None);
+
+        let sample = Engine::get_sample(&arguments, &response(), &request_times, 0, None, 0, false);
+
+        assert_eq!(
+            sample.phase_times[Phase::TimeToFirstByte as usize],
+            Some(millis(70))
+        );
+        assert_eq!(sample.phase_times[Phase::Connect as usize], None);
+    }
+
+    #[test]
+    fn status_codes_are_counted_apart_within_their_category() {
+        let mut arguments = Arguments::new("http://localhost:5500/".to_owned(), Method::GET);
+
+        arguments.assertions = vec![Assertion::parse("status:200,401,404,429").unwrap()];
+
+        let storage = Storage::from_arguments(&arguments).unwrap();
+        let mut recorder = Recorder::new(arguments.significant_figures);
+        let request_times = request_times(None);
+
+        for status in [200, 401, 404, 404, 429, 429, 429, 503] {
+            let response = response_with_status(StatusCode::from_u16(status).unwrap());
+            let sample =
+                Engine::get_sample(&arguments, &response, &request_times, 0, None, 0, false);
+
+            recorder.add_sample(&sample).unwrap();
+        }
+        storage.merge(&recorder).unwrap();
+
+        let status_codes = storage.get_status_code_response_times();
+        let client_error = StatusCodeCategory::ClientError;
+
+        assert_eq!(
+            status_codes.get_status_codes(client_error).unwrap(),
+            [401, 404, 429]
+        );
+        assert_eq!(
+            status_codes.get_requests_count(client_error, 401).unwrap(),
+            1
+        );
+        assert_eq!(
+            status_codes.get_requests_count(client_error, 404).unwrap(),
+            2
+        );
+        assert_eq!(
+            status_codes.get_requests_count(client_error, 429).unwrap(),
+            3
+        );
+        // 503 wasn't expected, so it's only counted as failing an assertion.
+        assert!(status_codes
+            .get_status_codes(StatusCodeCategory::ServerError)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            status_codes
+                .get_status_codes(StatusCodeCategory::AssertionFailed)
+                .unwrap(),
+            [503]
+        );
+        assert_eq!(
+            storage
+                .get_response_times()
+                .get_requests_count(StatusCodeCategory::AssertionFailed)
+                .unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn only_requests_with_a_response_count_bytes() {
+        let mut arguments = Arguments::new("http://localhost:5500/".to_owned(), Method::POST);
+
+        arguments.payload = Some("hello".to_owned());
+
+        let request_times = request_times(None);
+        let sample = Engine::get_sample(&arguments, &response(), &request_times, 0, None, 0, false);
+
+        assert_eq!(sample.sent_bytes, Some(5));
+        assert_eq!(sample.received_bytes, Some(2));
+
+        let error = reqwest::Client::new().get("not a url").build().unwrap_err();
+        let sample = Engine::get_sample(&arguments, &Err(error), &request_times, 0, None, 0, false);
+
+        assert!(sample.status_code_category == StatusCodeCategory::Failed);
+        assert_eq!(sample.sent_bytes, None);
+        assert_eq!(sample.received_bytes, None);
+    }
+
+    #[test]
+    fn byte_totals_leave_out_warmup_and_failed_requests() {
+        let arguments = Arguments::new("http://localhost:5500/".to_owned(), Method::POST);
+        let storage = Storage::from_arguments(&arguments).unwrap();
+        let mut recorder = Recorder::new(arguments.significant_figures);
+
+        for (sent_bytes, received_bytes, warmup) in [
+            (Some(5), Some(100), false),
+            (Some(5), Some(300), false),
+            (Some(5), Some(1000), true),
+            (None, None, false),
+        ] {
+            recorder
+                .add_sample(&Sample {
+                    status_code_category: match sent_bytes {
+                        Some(_) => StatusCodeCategory::Success,
+                        None => StatusCodeCategory::Failed,
+                    },
+                    sent_bytes,
+                    received_bytes,
+                    warmup,
+                    ..Sample::default()
+                })
+                .unwrap();
+        }
+        storage.merge(&recorder).unwrap();
+
+        assert_eq!(storage.get_sent_bytes(), 10);
+        assert_eq!(storage.get_received_bytes(), 400);
+        assert_eq!(
+            storage.get_response_times().get_responses_count().unwrap(),
+            2
+        );
+    }
+}
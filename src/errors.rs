@@ -2,30 +2,81 @@ use clap::error::ErrorKind;
 
 pub type AppResult<T> = Result<T, ErrorType>;
 
+pub const THRESHOLDS_FAILED_EXIT_CODE: i32 = 3;
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+#[derive(Debug)]
 pub enum ErrorType {
     InvalidHeaderStructure,
     InvalidHeaderName,
     InvalidHeaderValue,
     InvalidMethod,
+    InvalidEngine,
+    InvalidProfile,
+    ProfileFileReadFailed,
     InvalidProxy,
     HttpClientBuildFailed,
-    HttpRequestFailed,
+    AsyncRuntimeBuildFailed,
+    SignalHandlerFailed,
+    InvalidSignificantFigures,
+    InvalidOutputFormat,
+    OutputFileWriteFailed,
+    InvalidRequestLogFormat,
+    RequestLogWriteFailed,
+    InvalidSaveFilter,
+    InvalidAssertion,
+    ResponseSaveFailed,
+    InvalidThreshold,
+    ThresholdsFailed,
+    Interrupted,
+    InvalidConfigFormat,
+    ConfigFileReadFailed,
+    InvalidConfig(String),
     InvalidError,
 }
 
 impl ErrorType {
     pub fn to_command_error(&self) -> (ErrorKind, &str) {
-        match *self {
+        match self {
             ErrorType::InvalidHeaderStructure => {
                 (ErrorKind::InvalidValue, "Invalid header structure. Headers must be provided in the following format: <HEADER_NAME>:<HEADER_VALUE>")
             }
             ErrorType::InvalidHeaderName => (ErrorKind::InvalidValue, "Invalid header name. Can't parse header name."),
             ErrorType::InvalidHeaderValue => (ErrorKind::InvalidValue, "Invalid header value. Can't parse header value."),
             ErrorType::InvalidMethod => (ErrorKind::InvalidValue, "Invalid method. Please use one of the GET, POST, PUT, DELETE, HEAD and OPTIONS methods."),
+            ErrorType::InvalidEngine => (ErrorKind::InvalidValue, "Invalid engine. Please use one of the blocking and async engines."),
+            ErrorType::InvalidProfile => (ErrorKind::InvalidValue, "Invalid load profile. Stages must be provided in the following formats: ramp:<SECONDS>:<CONNECTIONS>, hold:<SECONDS>:<CONNECTIONS>, step:<SECONDS>:<INCREMENT>:<COUNT> or spike:<SECONDS>:<CONNECTIONS>"),
+            ErrorType::ProfileFileReadFailed => (ErrorKind::Io, "Failed to read load profile file. Please check the file path and permissions."),
             ErrorType::InvalidProxy => (ErrorKind::InvalidValue, "Invalid proxy. Please provide valid http, https or socks proxy."),
             ErrorType::HttpClientBuildFailed => (ErrorKind::Io, "Failed to build HTTP client. TLS backend can't initialized or system configuration can't load probably."),
-            ErrorType::HttpRequestFailed => (ErrorKind::Io, "HTTP request failed due to timeout reached."),
+            ErrorType::AsyncRuntimeBuildFailed => (ErrorKind::Io, "Failed to build async runtime. System resources for runtime threads can't be allocated probably."),
+            ErrorType::SignalHandlerFailed => (ErrorKind::Io, "Failed to set Ctrl-C signal handler."),
+            ErrorType::InvalidSignificantFigures => (ErrorKind::InvalidValue, "Invalid significant figures. Please provide a value between 1 and 5."),
+            ErrorType::InvalidOutputFormat => (ErrorKind::InvalidValue, "Invalid output format. Output file extension must be .json, .csv or .html."),
+            ErrorType::OutputFileWriteFailed => (ErrorKind::Io, "Failed to write output file. Please check the file path and permissions."),
+            ErrorType::InvalidRequestLogFormat => (ErrorKind::InvalidValue, "Invalid request log format. Request log file extension must be .csv or .jsonl."),
+            ErrorType::RequestLogWriteFailed => (ErrorKind::Io, "Failed to write request log file. Please check the file path and permissions."),
+            ErrorType::InvalidSaveFilter => (ErrorKind::InvalidValue, "Invalid save filter. Please use one of all, non-2xx or every:<N>."),
+            ErrorType::ResponseSaveFailed => (ErrorKind::Io, "Failed to save responses. Please check the file or directory path and permissions."),
+            ErrorType::InvalidAssertion => (ErrorKind::InvalidValue, "Invalid assertion. Assertions must be provided in the following formats: status:<CODES>, body-contains:<TEXT>, body-regex:<REGEX>, json:<PATH>=<VALUE>, header:<NAME> or max-latency:<MILLISECONDS>"),
+            ErrorType::InvalidThreshold => (ErrorKind::InvalidValue, "Invalid threshold. Thresholds must be provided in the following format: <METRIC><OPERATOR><VALUE>, e.g. p99<250ms, error_rate<1% or rps>1000"),
+            ErrorType::ThresholdsFailed => (ErrorKind::Io, "Load test failed thresholds."),
+            ErrorType::Interrupted => (ErrorKind::Io, "Load test interrupted, results are partial."),
+            ErrorType::InvalidConfigFormat => (ErrorKind::InvalidValue, "Invalid config file format. Config file extension must be .toml, .yaml or .yml."),
+            ErrorType::ConfigFileReadFailed => (ErrorKind::Io, "Failed to read config file. Please check the file path and permissions."),
+            ErrorType::InvalidConfig(message) => (ErrorKind::InvalidValue, message),
             ErrorType::InvalidError => (ErrorKind::InvalidValue, "Invalid error. Please report this error to our GitHub issues page."),
         }
     }
+
+    // Failed thresholds and interrupted runs exit with their own codes, so CI
+    // can tell a slow target or an aborted run apart from invalid arguments or
+    // a crashed run.
+    pub fn get_exit_code(&self) -> Option<i32> {
+        match *self {
+            ErrorType::ThresholdsFailed => Some(THRESHOLDS_FAILED_EXIT_CODE),
+            ErrorType::Interrupted => Some(INTERRUPTED_EXIT_CODE),
+            _ => None,
+        }
+    }
 }
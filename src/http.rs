@@ -1,13 +1,39 @@
-use std::time::Duration;
+use std::{
+    cell::Cell,
+    error::Error,
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex, OnceLock},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
 
-use reqwest::{blocking::Response, Method, Proxy, StatusCode};
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    header::HeaderMap,
+    Method, Proxy, StatusCode,
+};
+use rustls::{
+    client::{
+        ClientSessionMemoryCache, ClientSessionStore, Resumption, Tls12ClientSessionValue,
+        Tls13ClientSessionValue,
+    },
+    NamedGroup, RootCertStore,
+};
+use rustls_pki_types::ServerName;
+use tower_layer::Layer;
+use tower_service::Service;
 
 use crate::{
     app::Arguments,
     errors::{AppResult, ErrorType},
+    scenario::StepRequest,
 };
 
-#[derive(PartialEq, Eq, Hash)]
+pub const STATUS_CODE_CATEGORIES: usize = 7;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub enum StatusCodeCategory {
     Success,
     Redirection,
@@ -15,6 +41,33 @@ pub enum StatusCodeCategory {
     ServerError,
     Informational,
     Failed,
+    AssertionFailed,
+}
+
+impl StatusCodeCategory {
+    pub fn all() -> [Self; STATUS_CODE_CATEGORIES] {
+        [
+            Self::Success,
+            Self::Redirection,
+            Self::ClientError,
+            Self::ServerError,
+            Self::Informational,
+            Self::Failed,
+            Self::AssertionFailed,
+        ]
+    }
+
+    pub fn label(&self) -> &str {
+        match self {
+            Self::Success => "2xx",
+            Self::Redirection => "3xx",
+            Self::ClientError => "4xx",
+            Self::ServerError => "5xx",
+            Self::Informational => "1xx",
+            Self::Failed => "Failed",
+            Self::AssertionFailed => "Assertion failed",
+        }
+    }
 }
 
 impl From<StatusCode> for StatusCodeCategory {
@@ -33,9 +86,553 @@ impl From<StatusCode> for StatusCodeCategory {
     }
 }
 
+pub const PHASES: usize = 5;
+
+// Parts of a request timed on their own. DNS lookup, TCP connect and TLS
+// handshake are only timed when request needed a new connection, time to
+// first byte covers sending request until response headers arrive and
+// download covers reading the body.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Phase {
+    Dns,
+    Connect,
+    Tls,
+    TimeToFirstByte,
+    Download,
+}
+
+impl Phase {
+    pub fn all() -> [Self; PHASES] {
+        [
+            Self::Dns,
+            Self::Connect,
+            Self::Tls,
+            Self::TimeToFirstByte,
+            Self::Download,
+        ]
+    }
+
+    pub fn label(&self) -> &str {
+        match self {
+            Self::Dns => "DNS lookup",
+            Self::Connect => "TCP connect",
+            Self::Tls => "TLS handshake",
+            Self::TimeToFirstByte => "Time to first byte",
+            Self::Download => "Download",
+        }
+    }
+}
+
+// Instants a new connection went through, noted by resolver, connector and
+// TLS session store of the client that opened it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConnectTimes {
+    start_time: Option<Instant>,
+    dns_time: Option<Duration>,
+    tls_start_time: Option<Instant>,
+    end_time: Option<Instant>,
+}
+
+impl ConnectTimes {
+    // Connection opened without a resolver lookup or TLS handshake, for tests
+    // outside this module.
+    #[cfg(test)]
+    pub fn from_instants(start_time: Instant, end_time: Instant) -> Self {
+        Self {
+            start_time: Some(start_time),
+            end_time: Some(end_time),
+            ..Self::default()
+        }
+    }
+
+    // Connector resolves the name itself, TCP connect is what's left of its
+    // time until TLS handshake started.
+    pub fn get_phase_time(&self, phase: Phase) -> Option<Duration> {
+        let (start_time, end_time) = self.start_time.zip(self.end_time)?;
+
+        match phase {
+            Phase::Dns => self.dns_time,
+            Phase::Connect => Some(
+                (self.tls_start_time.unwrap_or(end_time) - start_time)
+                    .saturating_sub(self.dns_time.unwrap_or_default()),
+            ),
+            Phase::Tls => self
+                .tls_start_time
+                .map(|tls_start_time| end_time - tls_start_time),
+            Phase::TimeToFirstByte | Phase::Download => None,
+        }
+    }
+
+    pub fn get_total_time(&self) -> Duration {
+        match self.start_time.zip(self.end_time) {
+            Some((start_time, end_time)) => end_time - start_time,
+            None => Duration::ZERO,
+        }
+    }
+}
+
+tokio::task_local! {
+    static CONNECT_TIMES: Cell<ConnectTimes>;
+}
+
+// Where a client notes how its connections were opened. Async requests
+// sharing a client run concurrently, so each one notes them in its own task.
+// Blocking clients run requests on a runtime thread of their own and serve
+// one connection only, so they note them in one slot. Connections hyper
+// finishes in background after a pooled one turned up aren't timed.
+#[derive(Debug, Clone)]
+enum ConnectTimer {
+    Task,
+    Shared(Arc<Mutex<ConnectTimes>>),
+}
+
+impl ConnectTimer {
+    fn update<F>(&self, update: F)
+    where
+        F: FnOnce(&mut ConnectTimes),
+    {
+        match self {
+            Self::Task => {
+                let _ = CONNECT_TIMES.try_with(|connect_times| {
+                    let mut times = connect_times.get();
+
+                    update(&mut times);
+                    connect_times.set(times);
+                });
+            }
+            Self::Shared(connect_times) => {
+                if let Ok(mut connect_times) = connect_times.lock() {
+                    update(&mut connect_times);
+                }
+            }
+        }
+    }
+
+    fn take(&self) -> ConnectTimes {
+        match self {
+            Self::Task => CONNECT_TIMES.try_with(Cell::take).unwrap_or_default(),
+            Self::Shared(connect_times) => connect_times
+                .lock()
+                .map(|mut connect_times| std::mem::take(&mut *connect_times))
+                .unwrap_or_default(),
+        }
+    }
+
+    fn get_client_parts(
+        &self,
+    ) -> AppResult<(Arc<TimedResolver>, TimedConnectLayer, rustls::ClientConfig)> {
+        let tls_config = match rustls::ClientConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        {
+            Ok(tls_config) => tls_config,
+            Err(_) => return Err(ErrorType::HttpClientBuildFailed),
+        };
+        let mut tls_config = tls_config
+            .with_root_certificates(get_root_certificates())
+            .with_no_client_auth();
+
+        tls_config.resumption = Resumption::store(Arc::new(TimedSessionStore {
+            store: ClientSessionMemoryCache::new(SESSION_CACHE_SIZE),
+            connect_timer: self.clone(),
+        }));
+
+        Ok((
+            Arc::new(TimedResolver {
+                connect_timer: self.clone(),
+            }),
+            TimedConnectLayer {
+                connect_timer: self.clone(),
+            },
+            tls_config,
+        ))
+    }
+}
+
+// Resolves names the way reqwest's default resolver does and notes how long
+// lookup took.
+struct TimedResolver {
+    connect_timer: ConnectTimer,
+}
+
+impl Resolve for TimedResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let connect_timer = self.connect_timer.clone();
+
+        Box::pin(async move {
+            let start_time = Instant::now();
+            let addrs = tokio::net::lookup_host((name.as_str().to_owned(), 0)).await?;
+
+            connect_timer
+                .update(|connect_times| connect_times.dns_time = Some(start_time.elapsed()));
+
+            Ok(Box::new(addrs) as Addrs)
+        })
+    }
+}
+
+// Wraps reqwest's connector, which resolves, connects and does TLS handshake,
+// to note when opening a connection started and ended.
+#[derive(Clone)]
+struct TimedConnectLayer {
+    connect_timer: ConnectTimer,
+}
+
+impl<S> Layer<S> for TimedConnectLayer {
+    type Service = TimedConnector<S>;
+
+    fn layer(&self, connector: S) -> Self::Service {
+        TimedConnector {
+            connector,
+            connect_timer: self.connect_timer.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct TimedConnector<S> {
+    connector: S,
+    connect_timer: ConnectTimer,
+}
+
+impl<S, R> Service<R> for TimedConnector<S>
+where
+    S: Service<R>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.connector.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: R) -> Self::Future {
+        let connecting = self.connector.call(request);
+        let connect_timer = self.connect_timer.clone();
+
+        Box::pin(async move {
+            let start_time = Instant::now();
+            let connection = connecting.await;
+
+            connect_timer.update(|connect_times| {
+                connect_times.start_time = Some(start_time);
+                connect_times.end_time = Some(Instant::now());
+            });
+
+            connection
+        })
+    }
+}
+
+const SESSION_CACHE_SIZE: usize = 256;
+
+// rustls has no hook around handshake, but it looks up a session to resume
+// and key exchange hint of the server right before sending the first
+// handshake message, once TCP connection is open. Tests pin down that order.
+#[derive(Debug)]
+struct TimedSessionStore {
+    store: ClientSessionMemoryCache,
+    connect_timer: ConnectTimer,
+}
+
+impl TimedSessionStore {
+    fn note_tls_start(&self) {
+        let tls_start_time = Instant::now();
+
+        self.connect_timer.update(|connect_times| {
+            connect_times.tls_start_time.get_or_insert(tls_start_time);
+        });
+    }
+}
+
+impl ClientSessionStore for TimedSessionStore {
+    fn set_kx_hint(&self, server_name: ServerName<'static>, group: NamedGroup) {
+        self.store.set_kx_hint(server_name, group);
+    }
+
+    fn kx_hint(&self, server_name: &ServerName<'_>) -> Option<NamedGroup> {
+        self.note_tls_start();
+        self.store.kx_hint(server_name)
+    }
+
+    fn set_tls12_session(&self, server_name: ServerName<'static>, value: Tls12ClientSessionValue) {
+        self.store.set_tls12_session(server_name, value);
+    }
+
+    fn tls12_session(&self, server_name: &ServerName<'_>) -> Option<Tls12ClientSessionValue> {
+        self.note_tls_start();
+        self.store.tls12_session(server_name)
+    }
+
+    fn remove_tls12_session(&self, server_name: &ServerName<'static>) {
+        self.store.remove_tls12_session(server_name);
+    }
+
+    fn insert_tls13_ticket(
+        &self,
+        server_name: ServerName<'static>,
+        value: Tls13ClientSessionValue,
+    ) {
+        self.store.insert_tls13_ticket(server_name, value);
+    }
+
+    fn take_tls13_ticket(
+        &self,
+        server_name: &ServerName<'static>,
+    ) -> Option<Tls13ClientSessionValue> {
+        self.note_tls_start();
+        self.store.take_tls13_ticket(server_name)
+    }
+}
+
+// Certificates come from the platform trust store: Keychain on macOS, the
+// system store on Windows and the files OpenSSL would read elsewhere.
+// SSL_CERT_FILE and SSL_CERT_DIR replace them on every platform. They are
+// only read once for all clients.
+fn get_root_certificates() -> Arc<RootCertStore> {
+    static ROOT_CERTIFICATES: OnceLock<Arc<RootCertStore>> = OnceLock::new();
+
+    ROOT_CERTIFICATES
+        .get_or_init(|| {
+            let mut root_certificates = RootCertStore::empty();
+
+            root_certificates
+                .add_parsable_certificates(rustls_native_certs::load_native_certs().certs);
+
+            Arc::new(root_certificates)
+        })
+        .clone()
+}
+
+// Parts of a response kept after it has been received. Body is always read
+// to the end so connections can be kept alive, but only kept when something
+// needs it.
+pub struct HttpResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body_size: u64,
+    pub body: Option<Vec<u8>>,
+}
+
+impl HttpResponse {
+    pub fn from_blocking(
+        mut response: reqwest::blocking::Response,
+        keep_body: bool,
+    ) -> Result<Self, reqwest::Error> {
+        let status = response.status();
+        let headers = std::mem::take(response.headers_mut());
+        let (body_size, body) = match keep_body {
+            true => {
+                let body = response.bytes()?.to_vec();
+
+                (body.len() as u64, Some(body))
+            }
+            false => (response.copy_to(&mut io::sink())?, None),
+        };
+
+        Ok(Self {
+            status,
+            headers,
+            body_size,
+            body,
+        })
+    }
+
+    pub async fn from_async(
+        mut response: reqwest::Response,
+        keep_body: bool,
+    ) -> Result<Self, reqwest::Error> {
+        let status = response.status();
+        let headers = std::mem::take(response.headers_mut());
+        let (body_size, body) = match keep_body {
+            true => {
+                let body = response.bytes().await?.to_vec();
+
+                (body.len() as u64, Some(body))
+            }
+            false => {
+                let mut body_size = 0;
+
+                while let Some(chunk) = response.chunk().await? {
+                    body_size += chunk.len() as u64;
+                }
+
+                (body_size, None)
+            }
+        };
+
+        Ok(Self {
+            status,
+            headers,
+            body_size,
+            body,
+        })
+    }
+}
+
+pub const FAILURE_KINDS: usize = 10;
+
+// Why a request failed without getting a response from target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FailureKind {
+    Timeout,
+    ConnectionRefused,
+    ConnectionReset,
+    Dns,
+    Tls,
+    Connect,
+    Redirect,
+    Body,
+    Request,
+    Other,
+}
+
+impl FailureKind {
+    pub fn all() -> [Self; FAILURE_KINDS] {
+        [
+            Self::Timeout,
+            Self::ConnectionRefused,
+            Self::ConnectionReset,
+            Self::Dns,
+            Self::Tls,
+            Self::Connect,
+            Self::Redirect,
+            Self::Body,
+            Self::Request,
+            Self::Other,
+        ]
+    }
+
+    pub fn label(&self) -> &str {
+        match self {
+            Self::Timeout => "timeout",
+            Self::ConnectionRefused => "connection refused",
+            Self::ConnectionReset => "connection reset",
+            Self::Dns => "dns failure",
+            Self::Tls => "tls error",
+            Self::Connect => "connect",
+            Self::Redirect => "too many redirects",
+            Self::Body => "body read",
+            Self::Request => "request",
+            Self::Other => "other",
+        }
+    }
+
+    // reqwest only tells apart a few broad error kinds, the rest is found by
+    // walking down to the IO or rustls error that caused it. Resolver and
+    // hyper errors can't be downcast, they are told apart by their message.
+    fn from_source(error: &reqwest::Error) -> Option<Self> {
+        let mut source = error.source();
+
+        while let Some(error) = source {
+            if let Some(error) = error.downcast_ref::<io::Error>() {
+                if Self::is_tls_error(error) {
+                    return Some(Self::Tls);
+                }
+
+                match error.kind() {
+                    io::ErrorKind::ConnectionRefused => return Some(Self::ConnectionRefused),
+                    io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::UnexpectedEof => return Some(Self::ConnectionReset),
+                    _ => {}
+                }
+            }
+
+            let message = error.to_string().to_lowercase();
+
+            if message.contains("connection closed before message completed") {
+                return Some(Self::ConnectionReset);
+            }
+            if message.contains("dns error") || message.contains("failed to lookup address") {
+                return Some(Self::Dns);
+            }
+
+            source = error.source();
+        }
+
+        None
+    }
+
+    // rustls errors reach here wrapped into one or more IO errors, which don't
+    // return what they wrap as their source.
+    fn is_tls_error(error: &io::Error) -> bool {
+        match error.get_ref() {
+            Some(error) => {
+                error.is::<rustls::Error>()
+                    || error
+                        .downcast_ref::<io::Error>()
+                        .is_some_and(Self::is_tls_error)
+            }
+            None => false,
+        }
+    }
+
+    // Whole error chain in one line, top level reqwest messages alone don't
+    // say what actually went wrong. Some errors already print their source,
+    // those aren't repeated.
+    pub fn get_message(error: &(dyn Error + 'static)) -> String {
+        let mut message = Self::get_error_message(error);
+        let mut source = error.source();
+
+        while let Some(error) = source {
+            let source_message = Self::get_error_message(error);
+
+            if !message.ends_with(&source_message) {
+                message.push_str(": ");
+                message.push_str(&source_message);
+            }
+
+            source = error.source();
+        }
+
+        message
+    }
+
+    // Request URL is left out of reqwest messages the way
+    // `reqwest::Error::without_url` does, its query can carry credentials
+    // and messages end up in reports.
+    fn get_error_message(error: &(dyn Error + 'static)) -> String {
+        let message = error.to_string();
+
+        match error
+            .downcast_ref::<reqwest::Error>()
+            .and_then(reqwest::Error::url)
+        {
+            Some(url) => message.replace(&format!(" for url ({})", url), ""),
+            None => message,
+        }
+    }
+}
+
+impl From<&reqwest::Error> for FailureKind {
+    fn from(value: &reqwest::Error) -> Self {
+        if value.is_timeout() {
+            Self::Timeout
+        } else if value.is_redirect() {
+            Self::Redirect
+        } else if value.is_body() || value.is_decode() {
+            Self::Body
+        } else if let Some(failure_kind) = Self::from_source(value) {
+            failure_kind
+        } else if value.is_connect() {
+            Self::Connect
+        } else if value.is_request() {
+            Self::Request
+        } else {
+            Self::Other
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct HttpClientBlocking {
     client: reqwest::blocking::Client,
+    connect_timer: ConnectTimer,
     url: String,
     method: Method,
     payload: Option<String>,
@@ -43,7 +640,12 @@ pub struct HttpClientBlocking {
 
 impl HttpClientBlocking {
     pub fn from_arguments(arguments: &Arguments) -> AppResult<Self> {
-        let mut client_builder = reqwest::blocking::ClientBuilder::new();
+        let connect_timer = ConnectTimer::Shared(Arc::default());
+        let (resolver, connect_layer, tls_config) = connect_timer.get_client_parts()?;
+        let mut client_builder = reqwest::blocking::ClientBuilder::new()
+            .dns_resolver(resolver)
+            .connector_layer(connect_layer)
+            .use_preconfigured_tls(tls_config);
 
         if let Some(proxy_url) = &arguments.proxy {
             client_builder = client_builder.proxy(match Proxy::all(proxy_url) {
@@ -56,7 +658,9 @@ impl HttpClientBlocking {
         }
 
         client_builder = client_builder.timeout(Duration::from_secs(arguments.timeout));
-        client_builder = client_builder.tcp_keepalive(Duration::from_secs(arguments.duration));
+        if let Some(duration) = arguments.duration {
+            client_builder = client_builder.tcp_keepalive(Duration::from_secs(duration));
+        }
 
         let client = match client_builder.build() {
             Ok(client) => client,
@@ -65,24 +669,450 @@ impl HttpClientBlocking {
 
         Ok(Self {
             client,
+            connect_timer,
             url: arguments.target.clone(),
             method: arguments.method.clone(),
             payload: arguments.payload.clone(),
         })
     }
 
-    pub fn call(&self) -> AppResult<Response> {
+    // How the connection of the last request was opened, if it needed a new
+    // one.
+    pub fn take_connect_times(&self) -> ConnectTimes {
+        self.connect_timer.take()
+    }
+
+    pub fn call(&self) -> Result<reqwest::blocking::Response, reqwest::Error> {
         let mut request = self.client.request(self.method.clone(), self.url.clone());
 
         if let Some(payload) = &self.payload {
             request = request.body(payload.clone());
         }
 
-        let response = match request.send() {
-            Ok(response) => response,
-            Err(_) => return Err(ErrorType::HttpRequestFailed),
+        request.send()
+    }
+
+    pub fn send(
+        &self,
+        step_request: &StepRequest,
+    ) -> Result<reqwest::blocking::Response, reqwest::Error> {
+        let mut request = self
+            .client
+            .request(step_request.method.clone(), &step_request.url)
+            .headers(step_request.headers.clone());
+
+        if let Some(payload) = &step_request.payload {
+            request = request.body(payload.clone());
+        }
+
+        request.send()
+    }
+}
+
+#[derive(Clone)]
+pub struct HttpClientAsync {
+    client: reqwest::Client,
+    url: String,
+    method: Method,
+    payload: Option<String>,
+}
+
+impl HttpClientAsync {
+    pub fn from_arguments(arguments: &Arguments) -> AppResult<Self> {
+        let (resolver, connect_layer, tls_config) = ConnectTimer::Task.get_client_parts()?;
+        let mut client_builder = reqwest::ClientBuilder::new()
+            .dns_resolver(resolver)
+            .connector_layer(connect_layer)
+            .use_preconfigured_tls(tls_config);
+
+        if let Some(proxy_url) = &arguments.proxy {
+            client_builder = client_builder.proxy(match Proxy::all(proxy_url) {
+                Ok(proxy) => proxy,
+                Err(_) => return Err(ErrorType::InvalidProxy),
+            });
+        }
+        if let Some(headers) = &arguments.headers {
+            client_builder = client_builder.default_headers(headers.clone());
+        }
+
+        client_builder = client_builder.timeout(Duration::from_secs(arguments.timeout));
+        if let Some(duration) = arguments.duration {
+            client_builder = client_builder.tcp_keepalive(Duration::from_secs(duration));
+        }
+
+        let client = match client_builder.build() {
+            Ok(client) => client,
+            Err(_) => return Err(ErrorType::HttpClientBuildFailed),
         };
 
-        Ok(response)
+        Ok(Self {
+            client,
+            url: arguments.target.clone(),
+            method: arguments.method.clone(),
+            payload: arguments.payload.clone(),
+        })
+    }
+
+    // Runs a request and returns how its connection was opened, if it needed
+    // a new one.
+    pub async fn time_connect<F>(request: F) -> (F::Output, ConnectTimes)
+    where
+        F: Future,
+    {
+        CONNECT_TIMES
+            .scope(Cell::default(), async {
+                let output = request.await;
+
+                (output, ConnectTimer::Task.take())
+            })
+            .await
+    }
+
+    pub async fn call(&self) -> Result<reqwest::Response, reqwest::Error> {
+        let mut request = self.client.request(self.method.clone(), self.url.clone());
+
+        if let Some(payload) = &self.payload {
+            request = request.body(payload.clone());
+        }
+
+        request.send().await
+    }
+
+    pub async fn send(
+        &self,
+        step_request: &StepRequest,
+    ) -> Result<reqwest::Response, reqwest::Error> {
+        let mut request = self
+            .client
+            .request(step_request.method.clone(), &step_request.url)
+            .headers(step_request.headers.clone());
+
+        if let Some(payload) = &step_request.payload {
+            request = request.body(payload.clone());
+        }
+
+        request.send().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fmt,
+        io::Write,
+        net::{TcpListener, TcpStream},
+        thread,
+    };
+
+    use reqwest::Method;
+    use rustls::{ClientConfig, ClientConnection};
+
+    use super::*;
+
+    fn millis(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn connect_times(dns: Option<u64>, tls_start: Option<u64>) -> ConnectTimes {
+        let start_time = Instant::now();
+
+        ConnectTimes {
+            start_time: Some(start_time),
+            dns_time: dns.map(millis),
+            tls_start_time: tls_start.map(|tls_start| start_time + millis(tls_start)),
+            end_time: Some(start_time + millis(100)),
+        }
+    }
+
+    #[test]
+    fn connect_phases_split_connector_time() {
+        let connect_times = connect_times(Some(10), Some(40));
+
+        assert_eq!(connect_times.get_phase_time(Phase::Dns), Some(millis(10)));
+        assert_eq!(
+            connect_times.get_phase_time(Phase::Connect),
+            Some(millis(30))
+        );
+        assert_eq!(connect_times.get_phase_time(Phase::Tls), Some(millis(60)));
+        assert_eq!(connect_times.get_phase_time(Phase::TimeToFirstByte), None);
+        assert_eq!(connect_times.get_phase_time(Phase::Download), None);
+        assert_eq!(connect_times.get_total_time(), millis(100));
+    }
+
+    #[test]
+    fn plain_connection_is_all_connect_time() {
+        // Without a resolver lookup, as for IP targets, and without TLS.
+        let connect_times = connect_times(None, None);
+
+        assert_eq!(connect_times.get_phase_time(Phase::Dns), None);
+        assert_eq!(
+            connect_times.get_phase_time(Phase::Connect),
+            Some(millis(100))
+        );
+        assert_eq!(connect_times.get_phase_time(Phase::Tls), None);
+        assert_eq!(connect_times.get_total_time(), millis(100));
+    }
+
+    #[test]
+    fn pooled_connection_has_no_connect_phases() {
+        let connect_times = ConnectTimes {
+            dns_time: Some(millis(10)),
+            ..ConnectTimes::default()
+        };
+
+        for phase in Phase::all() {
+            assert_eq!(connect_times.get_phase_time(phase), None);
+        }
+        assert_eq!(connect_times.get_total_time(), Duration::ZERO);
+    }
+
+    // Notes every call rustls makes to the session store, in order.
+    #[derive(Debug, Default)]
+    struct RecordingSessionStore {
+        calls: Mutex<Vec<&'static str>>,
+    }
+
+    impl RecordingSessionStore {
+        fn note(&self, call: &'static str) {
+            self.calls.lock().unwrap().push(call);
+        }
+    }
+
+    impl ClientSessionStore for RecordingSessionStore {
+        fn set_kx_hint(&self, _: ServerName<'static>, _: NamedGroup) {
+            self.note("set_kx_hint");
+        }
+
+        fn kx_hint(&self, _: &ServerName<'_>) -> Option<NamedGroup> {
+            self.note("kx_hint");
+            None
+        }
+
+        fn set_tls12_session(&self, _: ServerName<'static>, _: Tls12ClientSessionValue) {
+            self.note("set_tls12_session");
+        }
+
+        fn tls12_session(&self, _: &ServerName<'_>) -> Option<Tls12ClientSessionValue> {
+            self.note("tls12_session");
+            None
+        }
+
+        fn remove_tls12_session(&self, _: &ServerName<'static>) {
+            self.note("remove_tls12_session");
+        }
+
+        fn insert_tls13_ticket(&self, _: ServerName<'static>, _: Tls13ClientSessionValue) {
+            self.note("insert_tls13_ticket");
+        }
+
+        fn take_tls13_ticket(&self, _: &ServerName<'static>) -> Option<Tls13ClientSessionValue> {
+            self.note("take_tls13_ticket");
+            None
+        }
+    }
+
+    fn tls_config(store: Arc<dyn ClientSessionStore>) -> ClientConfig {
+        let mut tls_config =
+            ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_root_certificates(RootCertStore::empty())
+                .with_no_client_auth();
+
+        tls_config.resumption = Resumption::store(store);
+        tls_config
+    }
+
+    fn server_name() -> ServerName<'static> {
+        ServerName::try_from("localhost").unwrap()
+    }
+
+    // TLS start is noted from these lookups, an upgrade of rustls moving or
+    // dropping them breaks TLS handshake timing.
+    #[test]
+    fn rustls_looks_up_sessions_while_writing_client_hello() {
+        let store = Arc::new(RecordingSessionStore::default());
+        let mut connection =
+            ClientConnection::new(Arc::new(tls_config(store.clone())), server_name()).unwrap();
+
+        assert_eq!(
+            *store.calls.lock().unwrap(),
+            ["take_tls13_ticket", "tls12_session", "kx_hint"]
+        );
+
+        let mut client_hello = Vec::new();
+
+        connection.write_tls(&mut client_hello).unwrap();
+
+        assert!(!client_hello.is_empty());
+        assert_eq!(store.calls.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn session_store_notes_first_lookup_as_tls_start() {
+        let connect_times = Arc::new(Mutex::new(ConnectTimes::default()));
+        let store = Arc::new(TimedSessionStore {
+            store: ClientSessionMemoryCache::new(SESSION_CACHE_SIZE),
+            connect_timer: ConnectTimer::Shared(connect_times.clone()),
+        });
+        let before_time = Instant::now();
+
+        ClientConnection::new(Arc::new(tls_config(store)), server_name()).unwrap();
+
+        let tls_start_time = connect_times.lock().unwrap().tls_start_time.unwrap();
+
+        assert!(tls_start_time >= before_time);
+        assert!(tls_start_time <= Instant::now());
+    }
+
+    // Serves every connection with `serve` on a local port.
+    fn spawn_server<F>(serve: F) -> String
+    where
+        F: Fn(TcpStream) + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                serve(stream);
+            }
+        });
+
+        address.to_string()
+    }
+
+    fn call(target: String, timeout: u64) -> reqwest::Error {
+        let mut arguments = Arguments::new(target, Method::GET);
+
+        arguments.timeout = timeout;
+
+        HttpClientBlocking::from_arguments(&arguments)
+            .unwrap()
+            .call()
+            .unwrap_err()
+    }
+
+    #[test]
+    fn refused_connection_is_classified() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        drop(listener);
+
+        let error = call(format!("http://{}/", address), 5);
+
+        assert_eq!(FailureKind::from(&error), FailureKind::ConnectionRefused);
+        assert!(FailureKind::get_message(&error).contains("tcp connect error"));
+    }
+
+    #[test]
+    fn reset_connection_is_classified() {
+        // Closing a socket with unread request data in it resets the
+        // connection.
+        let address = spawn_server(|stream| {
+            thread::sleep(Duration::from_millis(100));
+            drop(stream);
+        });
+
+        let error = call(format!("http://{}/", address), 5);
+
+        assert_eq!(FailureKind::from(&error), FailureKind::ConnectionReset);
+    }
+
+    #[test]
+    fn connection_closed_without_response_is_classified_as_reset() {
+        let address = spawn_server(|mut stream| {
+            let _ = io::Read::read(&mut stream, &mut [0; 1024]);
+        });
+
+        let error = call(format!("http://{}/", address), 5);
+
+        assert_eq!(FailureKind::from(&error), FailureKind::ConnectionReset);
+    }
+
+    #[test]
+    fn unresolvable_host_is_classified() {
+        // Names under .invalid never resolve.
+        let error = call("http://cicadas.invalid/".to_owned(), 5);
+
+        assert_eq!(FailureKind::from(&error), FailureKind::Dns);
+        assert!(FailureKind::get_message(&error).contains("dns error"));
+    }
+
+    #[test]
+    fn unanswered_request_is_classified_as_timeout() {
+        let address = spawn_server(|stream| {
+            thread::sleep(Duration::from_secs(2));
+            drop(stream);
+        });
+
+        let error = call(format!("http://{}/", address), 1);
+
+        assert_eq!(FailureKind::from(&error), FailureKind::Timeout);
+    }
+
+    #[test]
+    fn plain_response_to_https_request_is_classified_as_tls() {
+        let address = spawn_server(|mut stream| {
+            let _ = stream.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n");
+            thread::sleep(Duration::from_millis(200));
+        });
+
+        let error = call(format!("https://{}/", address), 5);
+
+        assert_eq!(FailureKind::from(&error), FailureKind::Tls);
+    }
+
+    #[derive(Debug)]
+    struct ChainError {
+        message: &'static str,
+        source: Option<Box<ChainError>>,
+    }
+
+    impl fmt::Display for ChainError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(self.message)
+        }
+    }
+
+    impl Error for ChainError {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            self.source.as_deref().map(|source| source as &dyn Error)
+        }
+    }
+
+    fn chain_error(messages: &[&'static str]) -> ChainError {
+        ChainError {
+            message: messages[0],
+            source: messages
+                .get(1..)
+                .filter(|messages| !messages.is_empty())
+                .map(|messages| Box::new(chain_error(messages))),
+        }
+    }
+
+    #[test]
+    fn message_joins_error_chain_without_repeating_sources() {
+        let error = chain_error(&[
+            "error sending request",
+            "connect failed: timed out",
+            "timed out",
+            "os error 110",
+        ]);
+
+        assert_eq!(
+            FailureKind::get_message(&error),
+            "error sending request: connect failed: timed out: os error 110"
+        );
+    }
+
+    #[test]
+    fn message_leaves_out_request_url() {
+        let error = call("http://127.0.0.1:1/?api_key=secret".to_owned(), 5);
+        let message = FailureKind::get_message(&error);
+
+        assert!(message.starts_with("error sending request: "));
+        assert!(!message.contains("secret"));
     }
 }
@@ -0,0 +1,22 @@
+pub mod app;
+pub mod assertion;
+pub mod capture;
+pub mod chart;
+pub mod config;
+pub mod dashboard;
+pub mod engine;
+pub mod errors;
+pub mod http;
+pub mod logger;
+pub mod output;
+pub mod pool;
+pub mod profile;
+pub mod recorder;
+pub mod request_log;
+pub mod scenario;
+pub mod scheduler;
+pub mod storage;
+#[cfg(test)]
+mod testing;
+pub mod threshold;
+pub mod writer;
@@ -1,8 +1,18 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use prettytable::{row, Table};
 
-use crate::{app::Arguments, errors::AppResult, http::StatusCodeCategory, storage::Storage};
+use crate::{
+    app::Arguments,
+    errors::AppResult,
+    http::{FailureKind, Phase, StatusCodeCategory},
+    profile::Profile,
+    recorder::Failures,
+    scenario::Scenario,
+    storage::{PhaseResponseTimes, ResponseTimes, StatusCodeResponseTimes, Storage},
+};
+
+pub const PERCENTILES: [f64; 6] = [50.0, 75.0, 90.0, 95.0, 99.0, 99.9];
 
 pub struct Logger;
 
@@ -13,94 +23,661 @@ impl Logger {
             arguments.method, arguments.target
         );
 
+        if storage.is_interrupted() {
+            println!("Load test interrupted, showing partial results.")
+        }
         if let Some(proxy) = &arguments.proxy {
             println!("Using proxy: {}", proxy)
         }
+        if let Some(rate) = arguments.rate {
+            println!("Using constant rate: {} requests per second", rate)
+        }
+        if arguments.warmup != 0 {
+            println!("Using warm-up: {} seconds", arguments.warmup)
+        }
+
+        if let Some(scenario) = &arguments.scenario {
+            println!("Using scenario: {} steps", scenario.get_steps().len())
+        }
 
+        if let Some(profile) = &arguments.profile {
+            println!("Using load profile: {} stages", profile.get_stages().len());
+            println!(
+                "Total {} requests have been sent over {:.2} seconds and up to {} connections.",
+                storage.get_response_times().get_total_requests_count()?,
+                storage.get_elapsed_time()?.as_secs_f64(),
+                arguments.connections
+            );
+        } else {
+            println!(
+                "Total {} requests have been sent over {:.2} seconds and {} connections.",
+                storage.get_response_times().get_total_requests_count()?,
+                storage.get_elapsed_time()?.as_secs_f64(),
+                arguments.connections
+            );
+        }
         println!(
-            "Total {} requests have been sent over {} seconds and {} connections.",
-            storage.get_total_requests_count()?,
-            arguments.duration,
-            arguments.connections
+            "Average response time: {:.2} ms",
+            storage.get_response_times().get_total_avg_response_time()?
         );
+
+        let mut categories_requests_per_second = Vec::new();
+
+        for status_code_category in StatusCodeCategory::all() {
+            let requests_count = storage
+                .get_response_times()
+                .get_requests_count(status_code_category)?;
+
+            if requests_count != 0 {
+                categories_requests_per_second.push(format!(
+                    "{}: {:.2}",
+                    status_code_category.label(),
+                    storage.get_requests_per_second(requests_count)?
+                ));
+            }
+        }
+
         println!(
-            "Average response time: {} ms \n",
-            storage.get_total_avg_response_time()?
+            "Requests per second: {:.2} ({})",
+            storage.get_requests_per_second(
+                storage.get_response_times().get_total_requests_count()?
+            )?,
+            categories_requests_per_second.join(", ")
         );
 
+        let responses_count = storage.get_response_times().get_responses_count()?;
+
+        println!(
+            "Received: {} ({}/s), average response size: {}",
+            Self::format_bytes(storage.get_received_bytes() as f64),
+            Self::format_bytes(storage.get_per_second(storage.get_received_bytes() as f64)?),
+            Self::format_bytes(Storage::get_rate(
+                storage.get_received_bytes() as f64,
+                responses_count as f64
+            ))
+        );
+        println!(
+            "Sent: {} ({}/s)",
+            Self::format_bytes(storage.get_sent_bytes() as f64),
+            Self::format_bytes(storage.get_per_second(storage.get_sent_bytes() as f64)?)
+        );
+
+        Self::show_rate_stats(&storage)?;
+
+        println!();
+
         Ok(())
     }
 
-    pub fn show_results(storage: Arc<Storage>) -> AppResult<()> {
+    pub fn show_results(arguments: &Arguments, storage: Arc<Storage>) -> AppResult<()> {
         println!("Detailed Results");
 
+        Self::show_response_times(
+            storage.get_response_times(),
+            Some(storage.get_status_code_response_times()),
+        )?;
+
+        if storage
+            .get_phase_response_times()
+            .get_requests_count(Phase::TimeToFirstByte)?
+            != 0
+        {
+            println!("\nLatency Phases");
+
+            Self::show_phases(storage.get_phase_response_times())?;
+        }
+
+        let failures = storage.get_failures()?;
+
+        if !failures.is_empty() {
+            println!("\nFailures");
+
+            Self::show_failures(&failures);
+        }
+
+        if storage
+            .get_corrected_response_times()
+            .get_total_requests_count()?
+            != 0
+        {
+            println!("\nMeasured and Corrected Results (corrected from intended send time)");
+
+            Self::show_corrected_response_times(
+                storage.get_response_times(),
+                storage.get_corrected_response_times(),
+            )?;
+        }
+
+        if let Some(profile) = &arguments.profile {
+            println!("\nResults per Stage");
+
+            Self::show_stages(
+                profile,
+                storage.get_stage_response_times(),
+                storage.get_elapsed_time()?,
+            )?;
+        }
+
+        if let Some(scenario) = &arguments.scenario {
+            println!("\nResults per Step");
+
+            Self::show_steps(scenario, &storage)?;
+        }
+
+        if storage
+            .get_warmup_response_times()
+            .get_total_requests_count()?
+            != 0
+        {
+            println!("\nWarm-up Results (excluded from other results)");
+
+            Self::show_response_times(storage.get_warmup_response_times(), None)?;
+        }
+
+        Ok(())
+    }
+
+    // Returns whether every threshold passed, no thresholds always pass.
+    pub fn show_thresholds(arguments: &Arguments, storage: Arc<Storage>) -> AppResult<bool> {
+        if arguments.thresholds.is_empty() {
+            return Ok(true);
+        }
+
+        println!("\nThresholds");
+
+        let mut table = Table::new();
+        let mut passed = true;
+
+        table.add_row(row!["Threshold", "Actual", "Result"]);
+
+        for threshold in &arguments.thresholds {
+            let actual_value = threshold.get_actual_value(&storage)?;
+            let threshold_passed = threshold.is_passed(actual_value);
+
+            passed &= threshold_passed;
+
+            table.add_row(row![
+                threshold.expression,
+                actual_value.map_or("n/a".to_owned(), |actual_value| threshold
+                    .format_value(actual_value)),
+                if threshold_passed { "Pass" } else { "Fail" }
+            ]);
+        }
+
+        table.printstd();
+
+        Ok(passed)
+    }
+
+    fn show_stages(
+        profile: &Profile,
+        stage_response_times: &[ResponseTimes],
+        elapsed_time: Duration,
+    ) -> AppResult<()> {
+        let mut table = Table::new();
+
+        table.add_row(row![
+            "Stage",
+            "Load",
+            "Duration (s)",
+            "Total",
+            "Req/s",
+            "Avg (ms)",
+            "P50 (ms)",
+            "P90 (ms)",
+            "P99 (ms)",
+            "Highest (ms)",
+            "Non 2xx"
+        ]);
+
+        let mut stage_start = Duration::ZERO;
+
+        for (index, (stage, response_times)) in profile
+            .get_stages()
+            .iter()
+            .zip(stage_response_times.iter())
+            .enumerate()
+        {
+            // Stages cut short by an interrupted load test only count the
+            // time they actually ran.
+            let stage_elapsed_time = elapsed_time
+                .saturating_sub(stage_start)
+                .min(Duration::from_secs(stage.duration));
+            let total_requests_count = response_times.get_total_requests_count()?;
+            let requests_per_second = Storage::get_rate(
+                total_requests_count as f64,
+                stage_elapsed_time.as_secs_f64(),
+            );
+
+            stage_start += Duration::from_secs(stage.duration);
+
+            table.add_row(row![
+                index + 1,
+                stage.label(),
+                stage.duration,
+                total_requests_count,
+                format!("{:.2}", requests_per_second),
+                Self::format_number(response_times.get_total_avg_response_time()?),
+                Self::format_number(response_times.get_total_percentile_response_time(50.0)?),
+                Self::format_number(response_times.get_total_percentile_response_time(90.0)?),
+                Self::format_number(response_times.get_total_percentile_response_time(99.0)?),
+                Self::format_number(response_times.get_total_max_response_time()?),
+                total_requests_count
+                    - response_times.get_requests_count(StatusCodeCategory::Success)?,
+            ]);
+        }
+
+        table.printstd();
+
+        Ok(())
+    }
+
+    fn show_steps(scenario: &Scenario, storage: &Storage) -> AppResult<()> {
+        let mut table = Table::new();
+
+        table.add_row(row![
+            "Step",
+            "Name",
+            "Total",
+            "Req/s",
+            "Avg (ms)",
+            "P50 (ms)",
+            "P90 (ms)",
+            "P99 (ms)",
+            "Highest (ms)",
+            "Non 2xx"
+        ]);
+
+        for (index, (step, response_times)) in scenario
+            .get_steps()
+            .iter()
+            .zip(storage.get_step_response_times().iter())
+            .enumerate()
+        {
+            let total_requests_count = response_times.get_total_requests_count()?;
+
+            table.add_row(row![
+                index + 1,
+                step.name,
+                total_requests_count,
+                format!(
+                    "{:.2}",
+                    storage.get_requests_per_second(total_requests_count)?
+                ),
+                Self::format_number(response_times.get_total_avg_response_time()?),
+                Self::format_number(response_times.get_total_percentile_response_time(50.0)?),
+                Self::format_number(response_times.get_total_percentile_response_time(90.0)?),
+                Self::format_number(response_times.get_total_percentile_response_time(99.0)?),
+                Self::format_number(response_times.get_total_max_response_time()?),
+                total_requests_count
+                    - response_times.get_requests_count(StatusCodeCategory::Success)?,
+            ]);
+        }
+
+        table.printstd();
+
+        Ok(())
+    }
+
+    // Exact status codes, when given, are listed right under the category
+    // they were counted in.
+    fn show_response_times(
+        response_times: &ResponseTimes,
+        status_codes: Option<&StatusCodeResponseTimes>,
+    ) -> AppResult<()> {
         let mut table = Table::new();
 
         table.add_row(row![
             "Type",
             "Total",
             "Avg (ms)",
+            "Stdev (ms)",
             "Lowest (ms)",
+            "P50 (ms)",
+            "P75 (ms)",
+            "P90 (ms)",
+            "P95 (ms)",
+            "P99 (ms)",
+            "P99.9 (ms)",
             "Highest (ms)"
         ]);
-        if storage.get_requests_count(StatusCodeCategory::Success)? != 0 {
-            table.add_row(row![
-                "2xx",
-                storage.get_requests_count(StatusCodeCategory::Success)?,
-                storage.get_avg_response_time(StatusCodeCategory::Success)?,
-                storage.get_min_reponse_time(StatusCodeCategory::Success)?,
-                storage.get_max_reponse_time(StatusCodeCategory::Success)?,
-            ]);
+
+        for status_code_category in StatusCodeCategory::all() {
+            if response_times.get_requests_count(status_code_category)? != 0 {
+                let percentiles = Self::get_percentiles(|percentile| {
+                    response_times.get_percentile_response_time(status_code_category, percentile)
+                })?;
+
+                table.add_row(row![
+                    status_code_category.label(),
+                    response_times.get_requests_count(status_code_category)?,
+                    Self::format_number(
+                        response_times.get_avg_response_time(status_code_category)?
+                    ),
+                    Self::format_number(
+                        response_times.get_std_deviation_response_time(status_code_category)?
+                    ),
+                    Self::format_number(response_times.get_min_reponse_time(status_code_category)?),
+                    Self::format_number(percentiles[0]),
+                    Self::format_number(percentiles[1]),
+                    Self::format_number(percentiles[2]),
+                    Self::format_number(percentiles[3]),
+                    Self::format_number(percentiles[4]),
+                    Self::format_number(percentiles[5]),
+                    Self::format_number(response_times.get_max_reponse_time(status_code_category)?),
+                ]);
+
+                if let Some(status_codes) = status_codes {
+                    Self::add_status_code_rows(&mut table, status_codes, status_code_category)?;
+                }
+            }
         }
-        if storage.get_requests_count(StatusCodeCategory::Redirection)? != 0 {
+
+        if response_times.get_total_requests_count()? != 0 {
+            let percentiles = Self::get_percentiles(|percentile| {
+                response_times.get_total_percentile_response_time(percentile)
+            })?;
+
             table.add_row(row![
-                "3xx",
-                storage.get_requests_count(StatusCodeCategory::Redirection)?,
-                storage.get_avg_response_time(StatusCodeCategory::Redirection)?,
-                storage.get_min_reponse_time(StatusCodeCategory::Redirection)?,
-                storage.get_max_reponse_time(StatusCodeCategory::Redirection)?,
+                "All",
+                response_times.get_total_requests_count()?,
+                Self::format_number(response_times.get_total_avg_response_time()?),
+                Self::format_number(response_times.get_total_std_deviation_response_time()?),
+                Self::format_number(response_times.get_total_min_response_time()?),
+                Self::format_number(percentiles[0]),
+                Self::format_number(percentiles[1]),
+                Self::format_number(percentiles[2]),
+                Self::format_number(percentiles[3]),
+                Self::format_number(percentiles[4]),
+                Self::format_number(percentiles[5]),
+                Self::format_number(response_times.get_total_max_response_time()?),
             ]);
         }
-        if storage.get_requests_count(StatusCodeCategory::ClientError)? != 0 {
+
+        table.printstd();
+
+        Ok(())
+    }
+
+    // Corrected response times only differ from measured ones when requests
+    // queued behind slow responses, so both are shown on the same rows.
+    fn show_corrected_response_times(
+        response_times: &ResponseTimes,
+        corrected_response_times: &ResponseTimes,
+    ) -> AppResult<()> {
+        let mut table = Table::new();
+
+        table.add_row(row!["Latency", "Measured (ms)", "Corrected (ms)"]);
+
+        let measured_percentiles = Self::get_percentiles(|percentile| {
+            response_times.get_total_percentile_response_time(percentile)
+        })?;
+        let corrected_percentiles = Self::get_percentiles(|percentile| {
+            corrected_response_times.get_total_percentile_response_time(percentile)
+        })?;
+
+        for (label, measured, corrected) in [
+            (
+                "Avg",
+                response_times.get_total_avg_response_time()?,
+                corrected_response_times.get_total_avg_response_time()?,
+            ),
+            (
+                "Stdev",
+                response_times.get_total_std_deviation_response_time()?,
+                corrected_response_times.get_total_std_deviation_response_time()?,
+            ),
+            (
+                "Lowest",
+                response_times.get_total_min_response_time()?,
+                corrected_response_times.get_total_min_response_time()?,
+            ),
+        ] {
             table.add_row(row![
-                "4xx",
-                storage.get_requests_count(StatusCodeCategory::ClientError)?,
-                storage.get_avg_response_time(StatusCodeCategory::ClientError)?,
-                storage.get_min_reponse_time(StatusCodeCategory::ClientError)?,
-                storage.get_max_reponse_time(StatusCodeCategory::ClientError)?,
+                label,
+                Self::format_number(measured),
+                Self::format_number(corrected)
             ]);
         }
-        if storage.get_requests_count(StatusCodeCategory::ServerError)? != 0 {
+
+        for ((percentile, measured), corrected) in PERCENTILES
+            .iter()
+            .zip(measured_percentiles)
+            .zip(corrected_percentiles)
+        {
             table.add_row(row![
-                "5xx",
-                storage.get_requests_count(StatusCodeCategory::ServerError)?,
-                storage.get_avg_response_time(StatusCodeCategory::ServerError)?,
-                storage.get_min_reponse_time(StatusCodeCategory::ServerError)?,
-                storage.get_max_reponse_time(StatusCodeCategory::ServerError)?,
+                format!("P{}", percentile),
+                Self::format_number(measured),
+                Self::format_number(corrected)
             ]);
         }
-        if storage.get_requests_count(StatusCodeCategory::Informational)? != 0 {
+
+        table.add_row(row![
+            "Highest",
+            Self::format_number(response_times.get_total_max_response_time()?),
+            Self::format_number(corrected_response_times.get_total_max_response_time()?)
+        ]);
+
+        table.printstd();
+
+        Ok(())
+    }
+
+    fn show_rate_stats(storage: &Storage) -> AppResult<()> {
+        let mut table = Table::new();
+
+        table.add_row(row!["Req/s", "Min", "Avg", "Max", "Stdev"]);
+
+        for (label, rate_stats) in [
+            ("Across seconds", storage.get_second_rate_stats()?),
+            ("Across connections", storage.get_worker_rate_stats()?),
+        ] {
+            if let Some(rate_stats) = rate_stats {
+                table.add_row(row![
+                    label,
+                    Self::format_number(rate_stats.min),
+                    Self::format_number(rate_stats.avg),
+                    Self::format_number(rate_stats.max),
+                    Self::format_number(rate_stats.stdev),
+                ]);
+            }
+        }
+
+        if table.len() > 1 {
+            table.printstd();
+        }
+
+        Ok(())
+    }
+
+    fn show_phases(phase_response_times: &PhaseResponseTimes) -> AppResult<()> {
+        let mut table = Table::new();
+
+        table.add_row(row![
+            "Phase",
+            "Total",
+            "Avg (ms)",
+            "Stdev (ms)",
+            "Lowest (ms)",
+            "P50 (ms)",
+            "P75 (ms)",
+            "P90 (ms)",
+            "P95 (ms)",
+            "P99 (ms)",
+            "P99.9 (ms)",
+            "Highest (ms)"
+        ]);
+
+        for phase in Phase::all() {
+            if phase_response_times.get_requests_count(phase)? == 0 {
+                continue;
+            }
+
+            let percentiles = Self::get_percentiles(|percentile| {
+                phase_response_times.get_percentile_response_time(phase, percentile)
+            })?;
+
             table.add_row(row![
-                "1xx",
-                storage.get_requests_count(StatusCodeCategory::Informational)?,
-                storage.get_avg_response_time(StatusCodeCategory::Informational)?,
-                storage.get_min_reponse_time(StatusCodeCategory::Informational)?,
-                storage.get_max_reponse_time(StatusCodeCategory::Informational)?,
+                phase.label(),
+                phase_response_times.get_requests_count(phase)?,
+                Self::format_number(phase_response_times.get_avg_response_time(phase)?),
+                Self::format_number(phase_response_times.get_std_deviation_response_time(phase)?),
+                Self::format_number(phase_response_times.get_min_reponse_time(phase)?),
+                Self::format_number(percentiles[0]),
+                Self::format_number(percentiles[1]),
+                Self::format_number(percentiles[2]),
+                Self::format_number(percentiles[3]),
+                Self::format_number(percentiles[4]),
+                Self::format_number(percentiles[5]),
+                Self::format_number(phase_response_times.get_max_reponse_time(phase)?),
             ]);
         }
-        if storage.get_requests_count(StatusCodeCategory::Failed)? != 0 {
+
+        table.printstd();
+
+        Ok(())
+    }
+
+    fn show_failures(failures: &[(FailureKind, Failures)]) {
+        let mut table = Table::new();
+
+        table.add_row(row!["Kind", "Total", "Sample errors"]);
+
+        for (failure_kind, failures) in failures {
             table.add_row(row![
-                "Failed",
-                storage.get_requests_count(StatusCodeCategory::Failed)?,
-                storage.get_avg_response_time(StatusCodeCategory::Failed)?,
-                storage.get_min_reponse_time(StatusCodeCategory::Failed)?,
-                storage.get_max_reponse_time(StatusCodeCategory::Failed)?,
+                failure_kind.label(),
+                failures.count,
+                failures.messages.join("\n")
             ]);
         }
 
-        //TODO: alien method! can causd panice
         table.printstd();
+    }
+
+    fn add_status_code_rows(
+        table: &mut Table,
+        status_codes: &StatusCodeResponseTimes,
+        status_code_category: StatusCodeCategory,
+    ) -> AppResult<()> {
+        for status_code in status_codes.get_status_codes(status_code_category)? {
+            let percentiles = Self::get_percentiles(|percentile| {
+                status_codes.get_percentile_response_time(
+                    status_code_category,
+                    status_code,
+                    percentile,
+                )
+            })?;
+
+            table.add_row(row![
+                format!("  {}", status_code),
+                status_codes.get_requests_count(status_code_category, status_code)?,
+                Self::format_number(
+                    status_codes.get_avg_response_time(status_code_category, status_code)?
+                ),
+                Self::format_number(
+                    status_codes
+                        .get_std_deviation_response_time(status_code_category, status_code)?
+                ),
+                Self::format_number(
+                    status_codes.get_min_reponse_time(status_code_category, status_code)?
+                ),
+                Self::format_number(percentiles[0]),
+                Self::format_number(percentiles[1]),
+                Self::format_number(percentiles[2]),
+                Self::format_number(percentiles[3]),
+                Self::format_number(percentiles[4]),
+                Self::format_number(percentiles[5]),
+                Self::format_number(
+                    status_codes.get_max_reponse_time(status_code_category, status_code)?
+                ),
+            ]);
+        }
 
         Ok(())
     }
+
+    fn get_percentiles<F>(get_percentile: F) -> AppResult<[f64; 6]>
+    where
+        F: Fn(f64) -> AppResult<f64>,
+    {
+        let mut percentiles = [0.0; 6];
+
+        for (index, percentile) in PERCENTILES.iter().enumerate() {
+            percentiles[index] = get_percentile(*percentile)?;
+        }
+
+        Ok(percentiles)
+    }
+
+    fn format_bytes(bytes: f64) -> String {
+        let units = ["B", "KB", "MB", "GB", "TB"];
+        let mut value = bytes;
+        let mut unit = 0;
+
+        while value >= 1024.0 && unit < units.len() - 1 {
+            value /= 1024.0;
+            unit += 1;
+        }
+
+        format!("{:.2} {}", value, units[unit])
+    }
+
+    fn format_number(millis: f64) -> String {
+        format!("{:.2}", millis)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::recorder::Recorder;
+
+    #[test]
+    fn status_code_rows_list_every_code_of_category() {
+        let status_codes = StatusCodeResponseTimes::new();
+        let mut histograms = HashMap::new();
+
+        for (status_code_category, status_code, requests) in [
+            (StatusCodeCategory::ClientError, 429, 3),
+            (StatusCodeCategory::ClientError, 401, 1),
+            (StatusCodeCategory::ClientError, 404, 2),
+            (StatusCodeCategory::AssertionFailed, 200, 1),
+        ] {
+            let mut histogram = Recorder::new_histogram(3).unwrap();
+
+            for _ in 0..requests {
+                Recorder::record(&mut histogram, Duration::from_millis(2)).unwrap();
+            }
+            histograms.insert((status_code_category, status_code), histogram);
+        }
+        status_codes
+            .merge(histograms.iter().map(|(key, histogram)| (*key, histogram)))
+            .unwrap();
+
+        let mut table = Table::new();
+
+        Logger::add_status_code_rows(&mut table, &status_codes, StatusCodeCategory::ClientError)
+            .unwrap();
+
+        let rows: Vec<(String, String)> = table
+            .row_iter()
+            .map(|row| {
+                (
+                    row.get_cell(0).unwrap().get_content(),
+                    row.get_cell(1).unwrap().get_content(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            rows,
+            [
+                ("  401".to_owned(), "1".to_owned()),
+                ("  404".to_owned(), "2".to_owned()),
+                ("  429".to_owned(), "3".to_owned()),
+            ]
+        );
+    }
 }
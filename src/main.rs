@@ -1,21 +1,19 @@
-use crate::{http::HttpClientBlocking, pool::ThreadPool};
-use app::App;
-use errors::AppResult;
-use http::StatusCodeCategory;
-use logger::Logger;
+use cicadas::{
+    app::App,
+    capture::ResponseCapture,
+    dashboard::Dashboard,
+    errors::{AppResult, ErrorType, INTERRUPTED_EXIT_CODE},
+    logger::Logger,
+    output::{FileManager, Format, Output},
+    request_log::RequestLog,
+    scheduler::Scheduler,
+    storage::Storage,
+};
 use std::{
+    io::{self, IsTerminal},
+    process,
     sync::Arc,
-    time::{Duration, Instant},
 };
-use storage::Storage;
-
-mod app;
-mod errors;
-mod http;
-mod logger;
-mod output;
-mod pool;
-mod storage;
 
 fn main() {
     let mut app = App::new();
@@ -27,44 +25,102 @@ fn main() {
 }
 
 fn app_handler(app: &App) -> AppResult<()> {
-    let mut thread_pool = ThreadPool::new();
     let arguments = Arc::new(app.get_arguments()?);
-    let storage = Arc::new(Storage::new());
-
-    for _ in 0..arguments.connections {
-        let storage = storage.clone();
-        let arguments = arguments.clone();
-
-        thread_pool.add(Box::new(move || {
-            let http_client = HttpClientBlocking::from_arguments(&arguments)?;
-            let start_time = Instant::now();
-
-            loop {
-                if Instant::now() - start_time < Duration::from_secs(arguments.duration) {
-                    let response_start_time = Instant::now();
-                    let response = http_client.call();
-                    let elapsed_response_time = Instant::now() - response_start_time;
-
-                    if let Ok(response) = response {
-                        storage
-                            .add_response_time(response.status().into(), elapsed_response_time)?;
-                    } else {
-                        storage
-                            .add_response_time(StatusCodeCategory::Failed, elapsed_response_time)?;
-                    }
-                } else {
-                    break;
-                }
-            }
-
-            Ok(())
-        }));
+    let storage = Arc::new(Storage::from_arguments(&arguments)?);
+    let scheduler = Arc::new(Scheduler::from_arguments(&arguments));
+
+    // Output files are created before load test starts, so an invalid path
+    // fails right away instead of after the whole run.
+    let mut outputs = Vec::with_capacity(arguments.output.len());
+
+    for path in &arguments.output {
+        outputs.push(Output::new(
+            Format::from_path(path)?,
+            FileManager::new(path.to_owned())?,
+        ));
+    }
+
+    let request_log = match &arguments.request_log {
+        Some(path) => Some(Arc::new(RequestLog::new(path)?)),
+        None => None,
+    };
+    let capture = match &arguments.save_responses {
+        Some(path) => Some(Arc::new(ResponseCapture::new(
+            path.to_owned(),
+            arguments.save_filter,
+        )?)),
+        None => None,
+    };
+
+    set_interrupt_handler(scheduler.clone())?;
+
+    // Live progress is only drawn on a terminal, redirected output would
+    // otherwise be filled with cursor movements.
+    let dashboard = if arguments.quiet || !io::stdout().is_terminal() {
+        None
+    } else {
+        Some(Dashboard::spawn(
+            arguments.clone(),
+            storage.clone(),
+            scheduler.clone(),
+        ))
+    };
+
+    let result = arguments.engine.run(
+        arguments.clone(),
+        storage.clone(),
+        scheduler,
+        request_log.clone(),
+        capture.clone(),
+    );
+
+    if let Some(dashboard) = dashboard {
+        dashboard.stop()?;
+    }
+    if let Some(request_log) = request_log {
+        request_log.finish()?;
+    }
+    if let Some(capture) = capture {
+        capture.finish()?;
     }
 
-    ThreadPool::wait_execution(thread_pool)?;
+    result?;
 
     Logger::show_overview(&arguments, storage.clone())?;
-    Logger::show_results(storage)?;
+    Logger::show_results(&arguments, storage.clone())?;
+
+    let thresholds_passed = Logger::show_thresholds(&arguments, storage.clone())?;
+
+    for output in &mut outputs {
+        output.write(&arguments, storage.clone())?;
+        output.flush()?;
+    }
+
+    // Partial results of an interrupted run can't gate anything, so it wins
+    // over failed thresholds.
+    if storage.is_interrupted() {
+        return Err(ErrorType::Interrupted);
+    }
+    if !thresholds_passed {
+        return Err(ErrorType::ThresholdsFailed);
+    }
 
     Ok(())
 }
+
+// First Ctrl-C stops load test and shows results collected so far, second one
+// exits right away without waiting for in flight requests.
+fn set_interrupt_handler(scheduler: Arc<Scheduler>) -> AppResult<()> {
+    let result = ctrlc::set_handler(move || {
+        if scheduler.is_stopped() {
+            process::exit(INTERRUPTED_EXIT_CODE);
+        }
+
+        scheduler.stop();
+    });
+
+    match result {
+        Ok(_) => Ok(()),
+        Err(_) => Err(ErrorType::SignalHandlerFailed),
+    }
+}
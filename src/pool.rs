@@ -2,45 +2,62 @@ use std::thread::{self, JoinHandle};
 
 use crate::errors::{AppResult, ErrorType};
 
-pub type Job = Box<dyn FnOnce() -> AppResult<()> + Send + 'static>;
+pub type Job<T> = Box<dyn FnOnce() -> AppResult<T> + Send + 'static>;
 
-pub struct Worker {
-    thread: JoinHandle<AppResult<()>>,
+pub struct Worker<T> {
+    thread: JoinHandle<AppResult<T>>,
 }
 
-impl Worker {
-    pub fn new(job: Job) -> Self {
-        let thread = thread::spawn(move || job());
+impl<T> Worker<T>
+where
+    T: Send + 'static,
+{
+    pub fn new(job: Job<T>) -> Self {
+        let thread = thread::spawn(job);
 
         Self { thread }
     }
 }
 
-pub struct ThreadPool {
-    workers: Vec<Worker>,
+pub struct ThreadPool<T> {
+    workers: Vec<Worker<T>>,
 }
 
-impl ThreadPool {
+impl<T> Default for ThreadPool<T>
+where
+    T: Send + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> ThreadPool<T>
+where
+    T: Send + 'static,
+{
     pub fn new() -> Self {
         let workers = Vec::new();
 
         Self { workers }
     }
 
-    pub fn add(&mut self, job: Job) {
+    pub fn add(&mut self, job: Job<T>) {
         let worker = Worker::new(job);
 
         self.workers.push(worker);
     }
 
-    pub fn wait_execution(pool: Self) -> AppResult<()> {
+    pub fn wait_execution(pool: Self) -> AppResult<Vec<T>> {
+        let mut results = Vec::with_capacity(pool.workers.len());
+
         for worker in pool.workers {
             match worker.thread.join() {
-                Ok(_) => {}
+                Ok(result) => results.push(result?),
                 Err(_) => return Err(ErrorType::InvalidError),
             }
         }
 
-        Ok(())
+        Ok(results)
     }
 }
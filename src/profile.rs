@@ -0,0 +1,363 @@
+use std::{fs, path::PathBuf, time::Duration};
+
+use crate::errors::{AppResult, ErrorType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StageKind {
+    Ramp,
+    Hold,
+    Step,
+    Spike,
+}
+
+#[derive(Debug, Clone)]
+pub struct Stage {
+    pub kind: StageKind,
+    pub duration: u64,
+    pub from: usize,
+    pub to: usize,
+}
+
+impl Stage {
+    pub fn label(&self) -> String {
+        match self.kind {
+            StageKind::Ramp => format!("ramp {} -> {}", self.from, self.to),
+            StageKind::Hold => format!("hold {}", self.to),
+            StageKind::Step => format!("step {}", self.to),
+            StageKind::Spike => format!("spike {}", self.to),
+        }
+    }
+
+    // Active connections count `elapsed` into this stage, ramps change it
+    // linearly between `from` and `to` over the stage duration.
+    fn get_connections(&self, elapsed: Duration) -> usize {
+        if self.from == self.to || self.duration == 0 {
+            return self.to;
+        }
+
+        let progress = (elapsed.as_secs_f64() / self.duration as f64).min(1.0);
+        let connections = self.from as f64 + (self.to as f64 - self.from as f64) * progress;
+
+        connections.floor() as usize
+    }
+
+    // How long into this stage connection number `worker` becomes active, if
+    // it does at all.
+    fn get_activation(&self, worker: usize) -> Option<Duration> {
+        if self.from > worker {
+            return Some(Duration::ZERO);
+        }
+        if self.to <= worker {
+            return None;
+        }
+
+        let progress = (worker + 1 - self.from) as f64 / (self.to - self.from) as f64;
+
+        Some(Duration::from_secs_f64(self.duration as f64 * progress))
+    }
+
+    // Part of this stage connection number `worker` is active during, ramps
+    // activate it partway up or release it partway down.
+    fn get_active_span(&self, worker: usize) -> Option<(Duration, Duration)> {
+        let duration = Duration::from_secs(self.duration);
+
+        if self.to > self.from {
+            return self
+                .get_activation(worker)
+                .map(|activation| (activation, duration));
+        }
+        if self.to <= worker && worker < self.from {
+            let progress = (self.from - worker - 1) as f64 / (self.from - self.to) as f64;
+
+            return Some((
+                Duration::ZERO,
+                Duration::from_secs_f64(self.duration as f64 * progress),
+            ));
+        }
+
+        (self.to > worker).then_some((Duration::ZERO, duration))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Profile {
+    stages: Vec<Stage>,
+}
+
+impl Profile {
+    pub fn parse(stages: &str) -> AppResult<Self> {
+        Self::parse_stages(stages.split(','))
+    }
+
+    pub fn from_file(path: PathBuf) -> AppResult<Self> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(_) => return Err(ErrorType::ProfileFileReadFailed),
+        };
+
+        Self::parse_stages(
+            content
+                .lines()
+                .map(|line| line.split('#').next().unwrap_or_default()),
+        )
+    }
+
+    fn parse_stages<'a, I>(stages: I) -> AppResult<Self>
+    where
+        I: Iterator<Item = &'a str>,
+    {
+        let mut parsed_stages: Vec<Stage> = Vec::new();
+        let mut connections = 0;
+
+        for stage in stages {
+            let stage = stage.trim();
+
+            if stage.is_empty() {
+                continue;
+            }
+
+            let splitted_stage: Vec<&str> = stage.split(':').collect();
+            let values = Self::parse_values(&splitted_stage[1..])?;
+
+            match (splitted_stage[0], values.as_slice()) {
+                ("ramp", [duration, to]) => {
+                    parsed_stages.push(Stage {
+                        kind: StageKind::Ramp,
+                        duration: *duration as u64,
+                        from: connections,
+                        to: *to,
+                    });
+                    connections = *to;
+                }
+                ("hold", [duration, to]) => {
+                    parsed_stages.push(Stage {
+                        kind: StageKind::Hold,
+                        duration: *duration as u64,
+                        from: *to,
+                        to: *to,
+                    });
+                    connections = *to;
+                }
+                ("step", [duration, increment, count]) => {
+                    for _ in 0..*count {
+                        connections += increment;
+
+                        parsed_stages.push(Stage {
+                            kind: StageKind::Step,
+                            duration: *duration as u64,
+                            from: connections,
+                            to: connections,
+                        });
+                    }
+                }
+                // Spikes don't change the base level, next stage continues
+                // from the connections count before the spike.
+                ("spike", [duration, to]) => parsed_stages.push(Stage {
+                    kind: StageKind::Spike,
+                    duration: *duration as u64,
+                    from: *to,
+                    to: *to,
+                }),
+                _ => return Err(ErrorType::InvalidProfile),
+            }
+        }
+
+        if parsed_stages.is_empty() || parsed_stages.iter().all(|stage| stage.duration == 0) {
+            return Err(ErrorType::InvalidProfile);
+        }
+
+        Ok(Self {
+            stages: parsed_stages,
+        })
+    }
+
+    fn parse_values(values: &[&str]) -> AppResult<Vec<usize>> {
+        let mut parsed_values = Vec::with_capacity(values.len());
+
+        for value in values {
+            match value.trim().parse() {
+                Ok(value) => parsed_values.push(value),
+                Err(_) => return Err(ErrorType::InvalidProfile),
+            }
+        }
+
+        Ok(parsed_values)
+    }
+
+    pub fn get_stages(&self) -> &[Stage] {
+        &self.stages
+    }
+
+    pub fn get_duration(&self) -> u64 {
+        self.stages.iter().map(|stage| stage.duration).sum()
+    }
+
+    pub fn get_max_connections(&self) -> usize {
+        self.stages
+            .iter()
+            .map(|stage| stage.from.max(stage.to))
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn get_stage_start(&self, index: usize) -> Duration {
+        Duration::from_secs(
+            self.stages[..index]
+                .iter()
+                .map(|stage| stage.duration)
+                .sum(),
+        )
+    }
+
+    pub fn get_stage_index(&self, elapsed: Duration) -> Option<usize> {
+        let mut stage_end = Duration::ZERO;
+
+        for (index, stage) in self.stages.iter().enumerate() {
+            stage_end += Duration::from_secs(stage.duration);
+
+            if elapsed < stage_end {
+                return Some(index);
+            }
+        }
+
+        None
+    }
+
+    pub fn get_connections(&self, elapsed: Duration) -> usize {
+        match self.get_stage_index(elapsed) {
+            Some(index) => {
+                self.stages[index].get_connections(elapsed - self.get_stage_start(index))
+            }
+            None => 0,
+        }
+    }
+
+    // How long connection number `worker` has been active for during the
+    // first `elapsed` of load test.
+    pub fn get_active_time(&self, worker: usize, elapsed: Duration) -> Duration {
+        let mut active_time = Duration::ZERO;
+
+        for (index, stage) in self.stages.iter().enumerate() {
+            if let Some((start, end)) = stage.get_active_span(worker) {
+                let stage_start = self.get_stage_start(index);
+
+                active_time += (stage_start + end)
+                    .min(elapsed)
+                    .saturating_sub(stage_start + start);
+            }
+        }
+
+        active_time
+    }
+
+    // Returns how long into load test connection number `worker` becomes
+    // active again, or `None` when it won't be needed anymore.
+    pub fn get_activation(&self, worker: usize, elapsed: Duration) -> Option<Duration> {
+        let current_index = self.get_stage_index(elapsed)?;
+
+        for (index, stage) in self.stages.iter().enumerate().skip(current_index) {
+            let activation = match stage.get_activation(worker) {
+                Some(activation) => self.get_stage_start(index) + activation,
+                None => continue,
+            };
+
+            if activation >= elapsed {
+                return Some(activation);
+            }
+            // Current stage is ramping up and about to reach this connection,
+            // otherwise it's ramping down and can't activate it anymore.
+            if stage.to > stage.from {
+                return Some(elapsed);
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: f64) -> Duration {
+        Duration::from_secs_f64(secs)
+    }
+
+    #[test]
+    fn ramp_up_activates_connections_linearly() {
+        let profile = Profile::parse("ramp:10:10").unwrap();
+
+        assert_eq!(profile.get_connections(secs(0.0)), 0);
+        assert_eq!(profile.get_connections(secs(5.0)), 5);
+        assert_eq!(profile.get_connections(secs(9.9)), 9);
+        assert_eq!(profile.get_activation(4, secs(0.0)), Some(secs(5.0)));
+        // A connection whose activation already passed starts right away.
+        assert_eq!(profile.get_activation(2, secs(5.0)), Some(secs(5.0)));
+    }
+
+    #[test]
+    fn ramp_down_never_reactivates_connections() {
+        let profile = Profile::parse("hold:5:10,ramp:10:0").unwrap();
+
+        assert_eq!(profile.get_connections(secs(2.0)), 10);
+        assert_eq!(profile.get_connections(secs(10.0)), 5);
+        assert_eq!(profile.get_connections(secs(14.9)), 0);
+        assert_eq!(profile.get_activation(7, secs(10.0)), None);
+    }
+
+    #[test]
+    fn ramp_down_then_up_reactivates_in_later_stage() {
+        let profile = Profile::parse("hold:5:10,ramp:5:0,ramp:5:10").unwrap();
+
+        assert_eq!(profile.get_connections(secs(8.0)), 4);
+        assert_eq!(profile.get_activation(7, secs(8.0)), Some(secs(14.0)));
+    }
+
+    #[test]
+    fn step_adds_connections_per_stage() {
+        let profile = Profile::parse("step:5:2:3").unwrap();
+
+        assert_eq!(profile.get_stages().len(), 3);
+        assert_eq!(profile.get_duration(), 15);
+        assert_eq!(profile.get_max_connections(), 6);
+        assert_eq!(profile.get_connections(secs(0.0)), 2);
+        assert_eq!(profile.get_connections(secs(5.0)), 4);
+        assert_eq!(profile.get_connections(secs(12.0)), 6);
+        assert_eq!(profile.get_activation(3, secs(0.0)), Some(secs(5.0)));
+        assert_eq!(profile.get_activation(5, secs(0.0)), Some(secs(10.0)));
+        assert_eq!(profile.get_activation(6, secs(0.0)), None);
+    }
+
+    #[test]
+    fn spike_returns_to_previous_level() {
+        let profile = Profile::parse("hold:5:2,spike:2:10,ramp:5:4").unwrap();
+
+        assert_eq!(profile.get_connections(secs(6.0)), 10);
+        assert_eq!(profile.get_connections(secs(7.0)), 2);
+        assert_eq!(profile.get_stages()[2].from, 2);
+        assert_eq!(profile.get_activation(5, secs(0.0)), Some(secs(5.0)));
+        assert_eq!(profile.get_activation(5, secs(7.5)), None);
+    }
+
+    #[test]
+    fn active_time_follows_ramps_up_and_down() {
+        let profile = Profile::parse("ramp:10:10,hold:5:10,ramp:10:0").unwrap();
+
+        // Connection 4 joins 5 seconds into the ramp up and leaves 5 seconds
+        // into the ramp down.
+        assert_eq!(profile.get_active_time(4, secs(25.0)), secs(15.0));
+        // The last connection leaves as soon as ramp down starts.
+        assert_eq!(profile.get_active_time(9, secs(25.0)), secs(5.0));
+        assert_eq!(profile.get_active_time(0, secs(25.0)), secs(23.0));
+        // Runs stopped early only count time until then.
+        assert_eq!(profile.get_active_time(4, secs(8.0)), secs(3.0));
+        assert_eq!(profile.get_active_time(10, secs(25.0)), Duration::ZERO);
+    }
+
+    #[test]
+    fn invalid_stages_are_rejected() {
+        assert!(Profile::parse("ramp:10").is_err());
+        assert!(Profile::parse("jump:10:10").is_err());
+        assert!(Profile::parse("hold:0:10").is_err());
+    }
+}
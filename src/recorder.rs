@@ -0,0 +1,444 @@
+use std::{
+    collections::{btree_map, hash_map::Entry, BTreeMap, HashMap},
+    time::{Duration, Instant},
+};
+
+use hdrhistogram::Histogram;
+
+use crate::{
+    errors::{AppResult, ErrorType},
+    http::{FailureKind, Phase, StatusCodeCategory, PHASES, STATUS_CODE_CATEGORIES},
+};
+
+// Highest trackable response time is one hour in microseconds, anything above
+// it will be clamped to this value so histograms can't grow beyond it.
+const HIGHEST_TRACKABLE_RESPONSE_TIME: u64 = 60 * 60 * 1_000_000;
+
+// Recorders get merged into storage at least this often while load test is
+// running, so live progress can be shown from storage.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+// Distinct error messages kept per failure kind, enough to show what went
+// wrong without holding every message of a failing run.
+const MAX_FAILURE_MESSAGES: usize = 3;
+
+type Histograms = [Option<Histogram<u64>>; STATUS_CODE_CATEGORIES];
+
+pub struct Sample {
+    pub worker: usize,
+    pub status_code_category: StatusCodeCategory,
+    pub status_code: Option<u16>,
+    pub failure_kind: Option<FailureKind>,
+    pub failure_message: Option<String>,
+    pub sent_bytes: Option<u64>,
+    pub received_bytes: Option<u64>,
+    pub response_time: Duration,
+    pub corrected_response_time: Option<Duration>,
+    pub phase_times: [Option<Duration>; PHASES],
+    pub stage: Option<usize>,
+    pub step: Option<usize>,
+    pub second: u64,
+    pub warmup: bool,
+}
+
+// Successful request of the first connection, tests and benchmarks override
+// only the fields they look at.
+impl Default for Sample {
+    fn default() -> Self {
+        Self {
+            worker: 0,
+            status_code_category: StatusCodeCategory::Success,
+            status_code: Some(200),
+            failure_kind: None,
+            failure_message: None,
+            sent_bytes: None,
+            received_bytes: None,
+            response_time: Duration::ZERO,
+            corrected_response_time: None,
+            phase_times: [None; PHASES],
+            stage: None,
+            step: None,
+            second: 0,
+            warmup: false,
+        }
+    }
+}
+
+pub struct Recorder {
+    significant_figures: u8,
+    response_times: Histograms,
+    corrected_response_times: Histograms,
+    stage_response_times: Vec<Histograms>,
+    step_response_times: Vec<Histograms>,
+    second_response_times: BTreeMap<u64, Histograms>,
+    warmup_response_times: Histograms,
+    status_code_response_times: HashMap<(StatusCodeCategory, u16), Histogram<u64>>,
+    failures: HashMap<FailureKind, Failures>,
+    phase_response_times: [Option<Histogram<u64>>; PHASES],
+    sent_bytes: u64,
+    received_bytes: u64,
+    worker_requests: Vec<u64>,
+    created_time: Instant,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Failures {
+    pub count: u64,
+    pub messages: Vec<String>,
+}
+
+impl Failures {
+    pub fn add_messages<'a, I>(&mut self, messages: I)
+    where
+        I: Iterator<Item = &'a String>,
+    {
+        for message in messages {
+            if self.messages.len() >= MAX_FAILURE_MESSAGES {
+                break;
+            }
+            if !self.messages.contains(message) {
+                self.messages.push(message.to_owned());
+            }
+        }
+    }
+}
+
+impl Recorder {
+    pub fn new(significant_figures: u8) -> Self {
+        Self {
+            significant_figures,
+            response_times: Default::default(),
+            corrected_response_times: Default::default(),
+            stage_response_times: Vec::new(),
+            step_response_times: Vec::new(),
+            second_response_times: BTreeMap::new(),
+            warmup_response_times: Default::default(),
+            status_code_response_times: HashMap::new(),
+            failures: HashMap::new(),
+            phase_response_times: Default::default(),
+            sent_bytes: 0,
+            received_bytes: 0,
+            worker_requests: Vec::new(),
+            created_time: Instant::now(),
+        }
+    }
+
+    pub fn is_flush_due(&self) -> bool {
+        self.created_time.elapsed() >= FLUSH_INTERVAL
+    }
+
+    pub fn new_histogram(significant_figures: u8) -> AppResult<Histogram<u64>> {
+        match Histogram::new(significant_figures) {
+            Ok(histogram) => Ok(histogram),
+            Err(_) => Err(ErrorType::InvalidSignificantFigures),
+        }
+    }
+
+    pub fn record(histogram: &mut Histogram<u64>, response_time: Duration) -> AppResult<()> {
+        let response_time = u64::try_from(response_time.as_micros())
+            .unwrap_or(u64::MAX)
+            .min(HIGHEST_TRACKABLE_RESPONSE_TIME);
+
+        match histogram.record(response_time) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(ErrorType::InvalidError),
+        }
+    }
+
+    fn record_category(
+        histograms: &mut Histograms,
+        significant_figures: u8,
+        status_code_category: StatusCodeCategory,
+        response_time: Duration,
+    ) -> AppResult<()> {
+        let histogram = &mut histograms[status_code_category as usize];
+
+        if histogram.is_none() {
+            *histogram = Some(Self::new_histogram(significant_figures)?);
+        }
+
+        match histogram {
+            Some(histogram) => Self::record(histogram, response_time),
+            None => Err(ErrorType::InvalidError),
+        }
+    }
+
+    fn record_indexed_category(
+        indexed_histograms: &mut Vec<Histograms>,
+        index: usize,
+        significant_figures: u8,
+        status_code_category: StatusCodeCategory,
+        response_time: Duration,
+    ) -> AppResult<()> {
+        if indexed_histograms.len() <= index {
+            indexed_histograms.resize_with(index + 1, Default::default);
+        }
+
+        Self::record_category(
+            &mut indexed_histograms[index],
+            significant_figures,
+            status_code_category,
+            response_time,
+        )
+    }
+
+    fn iter_categories(
+        histograms: &Histograms,
+    ) -> impl Iterator<Item = (StatusCodeCategory, &Histogram<u64>)> {
+        StatusCodeCategory::all()
+            .into_iter()
+            .zip(histograms.iter())
+            .filter_map(|(status_code_category, histogram)| {
+                histogram
+                    .as_ref()
+                    .map(|histogram| (status_code_category, histogram))
+            })
+    }
+
+    pub fn add_response_time(
+        &mut self,
+        status_code_category: StatusCodeCategory,
+        response_time: Duration,
+    ) -> AppResult<()> {
+        Self::record_category(
+            &mut self.response_times,
+            self.significant_figures,
+            status_code_category,
+            response_time,
+        )
+    }
+
+    pub fn add_corrected_response_time(
+        &mut self,
+        status_code_category: StatusCodeCategory,
+        corrected_response_time: Duration,
+    ) -> AppResult<()> {
+        Self::record_category(
+            &mut self.corrected_response_times,
+            self.significant_figures,
+            status_code_category,
+            corrected_response_time,
+        )
+    }
+
+    pub fn add_stage_response_time(
+        &mut self,
+        stage: usize,
+        status_code_category: StatusCodeCategory,
+        response_time: Duration,
+    ) -> AppResult<()> {
+        Self::record_indexed_category(
+            &mut self.stage_response_times,
+            stage,
+            self.significant_figures,
+            status_code_category,
+            response_time,
+        )
+    }
+
+    pub fn add_step_response_time(
+        &mut self,
+        step: usize,
+        status_code_category: StatusCodeCategory,
+        response_time: Duration,
+    ) -> AppResult<()> {
+        Self::record_indexed_category(
+            &mut self.step_response_times,
+            step,
+            self.significant_figures,
+            status_code_category,
+            response_time,
+        )
+    }
+
+    // Response times bucketed by the second of load test they completed in,
+    // keyed sparsely since a recorder only covers a second or two of the run.
+    pub fn add_second_response_time(
+        &mut self,
+        second: u64,
+        status_code_category: StatusCodeCategory,
+        response_time: Duration,
+    ) -> AppResult<()> {
+        let histograms = match self.second_response_times.entry(second) {
+            btree_map::Entry::Occupied(entry) => entry.into_mut(),
+            btree_map::Entry::Vacant(entry) => entry.insert(Default::default()),
+        };
+
+        Self::record_category(
+            histograms,
+            self.significant_figures,
+            status_code_category,
+            response_time,
+        )
+    }
+
+    // Response times per exact status code, kept under the category response
+    // was counted in so codes failing assertions stay apart.
+    pub fn add_status_code_response_time(
+        &mut self,
+        status_code_category: StatusCodeCategory,
+        status_code: u16,
+        response_time: Duration,
+    ) -> AppResult<()> {
+        let histogram = match self
+            .status_code_response_times
+            .entry((status_code_category, status_code))
+        {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(Self::new_histogram(self.significant_figures)?),
+        };
+
+        Self::record(histogram, response_time)
+    }
+
+    pub fn add_phase_time(&mut self, phase: Phase, phase_time: Duration) -> AppResult<()> {
+        let histogram = &mut self.phase_response_times[phase as usize];
+
+        if histogram.is_none() {
+            *histogram = Some(Self::new_histogram(self.significant_figures)?);
+        }
+
+        match histogram {
+            Some(histogram) => Self::record(histogram, phase_time),
+            None => Err(ErrorType::InvalidError),
+        }
+    }
+
+    pub fn add_failure(&mut self, failure_kind: FailureKind, message: Option<&String>) {
+        let failures = self.failures.entry(failure_kind).or_default();
+
+        failures.count += 1;
+        failures.add_messages(message.into_iter());
+    }
+
+    // Warm-up samples are kept apart so they never show up in results.
+    pub fn add_sample(&mut self, sample: &Sample) -> AppResult<()> {
+        if sample.warmup {
+            return Self::record_category(
+                &mut self.warmup_response_times,
+                self.significant_figures,
+                sample.status_code_category,
+                sample.response_time,
+            );
+        }
+
+        self.add_response_time(sample.status_code_category, sample.response_time)?;
+        self.add_second_response_time(
+            sample.second,
+            sample.status_code_category,
+            sample.response_time,
+        )?;
+
+        if self.worker_requests.len() <= sample.worker {
+            self.worker_requests.resize(sample.worker + 1, 0);
+        }
+
+        self.worker_requests[sample.worker] += 1;
+        self.sent_bytes += sample.sent_bytes.unwrap_or_default();
+        self.received_bytes += sample.received_bytes.unwrap_or_default();
+
+        for (phase, phase_time) in Phase::all().into_iter().zip(sample.phase_times) {
+            if let Some(phase_time) = phase_time {
+                self.add_phase_time(phase, phase_time)?;
+            }
+        }
+        if let Some(failure_kind) = sample.failure_kind {
+            self.add_failure(failure_kind, sample.failure_message.as_ref());
+        }
+        if let Some(status_code) = sample.status_code {
+            self.add_status_code_response_time(
+                sample.status_code_category,
+                status_code,
+                sample.response_time,
+            )?;
+        }
+        if let Some(corrected_response_time) = sample.corrected_response_time {
+            self.add_corrected_response_time(sample.status_code_category, corrected_response_time)?;
+        }
+        if let Some(stage) = sample.stage {
+            self.add_stage_response_time(stage, sample.status_code_category, sample.response_time)?;
+        }
+        if let Some(step) = sample.step {
+            self.add_step_response_time(step, sample.status_code_category, sample.response_time)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn get_response_times(
+        &self,
+    ) -> impl Iterator<Item = (StatusCodeCategory, &Histogram<u64>)> {
+        Self::iter_categories(&self.response_times)
+    }
+
+    pub fn get_corrected_response_times(
+        &self,
+    ) -> impl Iterator<Item = (StatusCodeCategory, &Histogram<u64>)> {
+        Self::iter_categories(&self.corrected_response_times)
+    }
+
+    pub fn get_warmup_response_times(
+        &self,
+    ) -> impl Iterator<Item = (StatusCodeCategory, &Histogram<u64>)> {
+        Self::iter_categories(&self.warmup_response_times)
+    }
+
+    pub fn get_stage_response_times(
+        &self,
+    ) -> impl Iterator<Item = impl Iterator<Item = (StatusCodeCategory, &Histogram<u64>)>> {
+        self.stage_response_times.iter().map(Self::iter_categories)
+    }
+
+    pub fn get_step_response_times(
+        &self,
+    ) -> impl Iterator<Item = impl Iterator<Item = (StatusCodeCategory, &Histogram<u64>)>> {
+        self.step_response_times.iter().map(Self::iter_categories)
+    }
+
+    pub fn get_second_response_times(
+        &self,
+    ) -> impl Iterator<
+        Item = (
+            u64,
+            impl Iterator<Item = (StatusCodeCategory, &Histogram<u64>)>,
+        ),
+    > {
+        self.second_response_times
+            .iter()
+            .map(|(second, histograms)| (*second, Self::iter_categories(histograms)))
+    }
+
+    pub fn get_status_code_response_times(
+        &self,
+    ) -> impl Iterator<Item = ((StatusCodeCategory, u16), &Histogram<u64>)> {
+        self.status_code_response_times
+            .iter()
+            .map(|(key, histogram)| (*key, histogram))
+    }
+
+    pub fn get_phase_response_times(&self) -> impl Iterator<Item = (Phase, &Histogram<u64>)> {
+        Phase::all()
+            .into_iter()
+            .zip(self.phase_response_times.iter())
+            .filter_map(|(phase, histogram)| histogram.as_ref().map(|histogram| (phase, histogram)))
+    }
+
+    pub fn get_sent_bytes(&self) -> u64 {
+        self.sent_bytes
+    }
+
+    pub fn get_received_bytes(&self) -> u64 {
+        self.received_bytes
+    }
+
+    // Requests sent by each connection, indexed by connection number.
+    pub fn get_worker_requests(&self) -> &[u64] {
+        &self.worker_requests
+    }
+
+    pub fn get_failures(&self) -> impl Iterator<Item = (FailureKind, &Failures)> {
+        self.failures
+            .iter()
+            .map(|(failure_kind, failures)| (*failure_kind, failures))
+    }
+}
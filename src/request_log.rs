@@ -0,0 +1,308 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::mpsc::Receiver,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::json;
+
+use crate::{
+    errors::{AppResult, ErrorType},
+    http::{FailureKind, StatusCodeCategory},
+    recorder::Sample,
+    writer::{BackgroundSender, BackgroundWriter},
+};
+
+// Records waiting to be written before connections block on sending, large
+// enough to absorb disk hiccups without letting memory grow unbounded.
+const CHANNEL_CAPACITY: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy)]
+pub enum RequestLogFormat {
+    Csv,
+    JsonLines,
+}
+
+impl RequestLogFormat {
+    pub fn from_path(path: &Path) -> AppResult<Self> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("csv") => Ok(Self::Csv),
+            Some("jsonl") => Ok(Self::JsonLines),
+            _ => Err(ErrorType::InvalidRequestLogFormat),
+        }
+    }
+}
+
+struct RequestRecord {
+    start_time: Instant,
+    worker: usize,
+    status_code: Option<u16>,
+    response_time_micros: u128,
+    received_bytes: Option<u64>,
+    failure_kind: Option<FailureKind>,
+    assertion_failed: bool,
+    warmup: bool,
+}
+
+// Streams one record per request into a file. Connections only push records
+// into a channel, formatting and disk writes happen on a background thread.
+pub struct RequestLog {
+    writer: BackgroundWriter<RequestRecord>,
+}
+
+impl RequestLog {
+    pub fn new(path: &Path) -> AppResult<Self> {
+        let format = RequestLogFormat::from_path(path)?;
+        let file = match File::create(path) {
+            Ok(file) => file,
+            Err(_) => return Err(ErrorType::RequestLogWriteFailed),
+        };
+
+        Ok(Self {
+            writer: BackgroundWriter::spawn(CHANNEL_CAPACITY, move |receiver| {
+                Self::write(format, BufWriter::new(file), receiver)
+            }),
+        })
+    }
+
+    pub fn get_sender(&self) -> AppResult<RequestLogSender> {
+        Ok(RequestLogSender {
+            sender: self.writer.get_sender()?,
+        })
+    }
+
+    // Waits for every pending record to be written.
+    pub fn finish(&self) -> AppResult<()> {
+        self.writer.finish()
+    }
+
+    fn write(
+        format: RequestLogFormat,
+        mut writer: BufWriter<File>,
+        receiver: Receiver<RequestRecord>,
+    ) -> AppResult<()> {
+        // Records carry monotonic instants, wall clock is only read once here
+        // to turn them into timestamps.
+        let base_instant = Instant::now();
+        let base_timestamp = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(timestamp) => timestamp,
+            Err(_) => return Err(ErrorType::InvalidError),
+        };
+
+        if let RequestLogFormat::Csv = format {
+            Self::write_line(
+                &mut writer,
+                "timestamp_us,worker,status_code,response_time_us,received_bytes,error,assertion_failed,warmup",
+            )?;
+        }
+
+        for record in receiver {
+            let timestamp = if record.start_time >= base_instant {
+                base_timestamp + (record.start_time - base_instant)
+            } else {
+                base_timestamp.saturating_sub(base_instant - record.start_time)
+            };
+
+            let line = match format {
+                RequestLogFormat::Csv => format!(
+                    "{},{},{},{},{},{},{},{}",
+                    timestamp.as_micros(),
+                    record.worker,
+                    record
+                        .status_code
+                        .map(|status_code| status_code.to_string())
+                        .unwrap_or_default(),
+                    record.response_time_micros,
+                    record
+                        .received_bytes
+                        .map(|received_bytes| received_bytes.to_string())
+                        .unwrap_or_default(),
+                    record
+                        .failure_kind
+                        .as_ref()
+                        .map(|failure_kind| failure_kind.label())
+                        .unwrap_or_default(),
+                    record.assertion_failed,
+                    record.warmup
+                ),
+                RequestLogFormat::JsonLines => json!({
+                    "timestamp_us": timestamp.as_micros() as u64,
+                    "worker": record.worker,
+                    "status_code": record.status_code,
+                    "response_time_us": record.response_time_micros as u64,
+                    "received_bytes": record.received_bytes,
+                    "error": record.failure_kind.as_ref().map(|failure_kind| failure_kind.label()),
+                    "assertion_failed": record.assertion_failed,
+                    "warmup": record.warmup,
+                })
+                .to_string(),
+            };
+
+            Self::write_line(&mut writer, &line)?;
+        }
+
+        match writer.flush() {
+            Ok(_) => Ok(()),
+            Err(_) => Err(ErrorType::RequestLogWriteFailed),
+        }
+    }
+
+    fn write_line(writer: &mut BufWriter<File>, line: &str) -> AppResult<()> {
+        match writeln!(writer, "{}", line) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(ErrorType::RequestLogWriteFailed),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RequestLogSender {
+    sender: BackgroundSender<RequestRecord>,
+}
+
+impl RequestLogSender {
+    fn get_record(start_time: Instant, sample: &Sample) -> RequestRecord {
+        RequestRecord {
+            start_time,
+            worker: sample.worker,
+            status_code: sample.status_code,
+            response_time_micros: sample.response_time.as_micros(),
+            received_bytes: sample.received_bytes,
+            failure_kind: sample.failure_kind,
+            assertion_failed: sample.status_code_category == StatusCodeCategory::AssertionFailed,
+            warmup: sample.warmup,
+        }
+    }
+
+    pub fn add(&self, start_time: Instant, sample: &Sample) -> AppResult<()> {
+        if !self.sender.send(Self::get_record(start_time, sample)) {
+            return Err(ErrorType::RequestLogWriteFailed);
+        }
+
+        Ok(())
+    }
+
+    pub async fn add_async(&self, start_time: Instant, sample: &Sample) -> AppResult<()> {
+        if !self
+            .sender
+            .send_async(Self::get_record(start_time, sample))
+            .await
+        {
+            return Err(ErrorType::RequestLogWriteFailed);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::Duration};
+
+    use super::*;
+    use crate::testing::temp_path;
+
+    fn sample(worker: usize, status_code: Option<u16>, warmup: bool) -> Sample {
+        let (status_code_category, failure_kind) = match status_code {
+            Some(_) => (StatusCodeCategory::AssertionFailed, None),
+            None => (StatusCodeCategory::Failed, Some(FailureKind::Timeout)),
+        };
+
+        Sample {
+            worker,
+            status_code_category,
+            status_code,
+            failure_kind,
+            received_bytes: status_code.map(|_| 20),
+            response_time: Duration::from_micros(1500),
+            warmup,
+            ..Sample::default()
+        }
+    }
+
+    fn write_lines(extension: &str) -> Vec<String> {
+        let path = temp_path(&format!("request-log.{}", extension));
+        let request_log = RequestLog::new(&path).unwrap();
+        let sender = request_log.get_sender().unwrap();
+        let start_time = Instant::now();
+
+        sender.add(start_time, &sample(0, Some(200), true)).unwrap();
+        sender
+            .add(
+                start_time + Duration::from_millis(2),
+                &sample(1, None, false),
+            )
+            .unwrap();
+        drop(sender);
+        request_log.finish().unwrap();
+
+        let lines = fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(str::to_owned)
+            .collect();
+
+        fs::remove_file(path).unwrap();
+
+        lines
+    }
+
+    // Timestamps depend on wall clock, so they're only checked to be 2ms
+    // apart.
+    fn split_timestamp(line: &str) -> (u128, &str) {
+        let (timestamp, rest) = line.split_once(',').unwrap();
+
+        (timestamp.parse().unwrap(), rest)
+    }
+
+    #[test]
+    fn writes_csv_records() {
+        let lines = write_lines("csv");
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0],
+            "timestamp_us,worker,status_code,response_time_us,received_bytes,error,assertion_failed,warmup"
+        );
+
+        let (first_timestamp, first) = split_timestamp(&lines[1]);
+        let (second_timestamp, second) = split_timestamp(&lines[2]);
+
+        assert_eq!(first, "0,200,1500,20,,true,true");
+        assert_eq!(second, "1,,1500,,timeout,false,false");
+        assert_eq!(second_timestamp - first_timestamp, 2000);
+    }
+
+    #[test]
+    fn writes_json_lines_records() {
+        let lines = write_lines("jsonl");
+        let records = lines
+            .iter()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["worker"], 0);
+        assert_eq!(records[0]["status_code"], 200);
+        assert_eq!(records[0]["response_time_us"], 1500);
+        assert_eq!(records[0]["received_bytes"], 20);
+        assert_eq!(records[0]["error"], serde_json::Value::Null);
+        assert_eq!(records[0]["assertion_failed"], true);
+        assert_eq!(records[0]["warmup"], true);
+        assert_eq!(records[1]["status_code"], serde_json::Value::Null);
+        assert_eq!(records[1]["error"], "timeout");
+        assert_eq!(records[1]["assertion_failed"], false);
+        assert_eq!(
+            records[1]["timestamp_us"].as_u64().unwrap()
+                - records[0]["timestamp_us"].as_u64().unwrap(),
+            2000
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_formats() {
+        assert!(RequestLog::new(Path::new("requests.txt")).is_err());
+    }
+}
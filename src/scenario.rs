@@ -0,0 +1,692 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Write,
+};
+
+use regex::bytes::Regex;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Method,
+};
+use serde_json::Value;
+
+use crate::{
+    app::App,
+    assertion::Assertion,
+    config::{Config, StepConfig},
+    errors::AppResult,
+    http::{HttpResponse, StatusCodeCategory},
+    recorder::Sample,
+};
+
+// Reads a value out of a step response into a variable, like
+// `json:$.token`, `header:location` or `regex:id=(\d+)`.
+#[derive(Debug, Clone)]
+pub enum Extractor {
+    Json(String),
+    Header(HeaderName),
+    Regex(Regex),
+}
+
+impl Extractor {
+    pub fn parse(extractor: &str) -> Option<Self> {
+        let (kind, value) = extractor.split_once(':')?;
+
+        match kind.trim().to_lowercase().as_str() {
+            "json" => Some(Self::Json(Assertion::to_json_pointer(value.trim()))),
+            "header" => value.trim().parse().ok().map(Self::Header),
+            "regex" => Regex::new(value).ok().map(Self::Regex),
+            _ => None,
+        }
+    }
+
+    pub fn needs_body(&self) -> bool {
+        matches!(self, Self::Json(_) | Self::Regex(_))
+    }
+
+    // JSON strings are taken without quotes, other JSON values as they're
+    // written. Regex takes its first group when it has one.
+    pub fn extract(&self, response: &HttpResponse) -> Option<String> {
+        let body = response.body.as_deref().unwrap_or_default();
+
+        match self {
+            Self::Json(pointer) => {
+                let json = serde_json::from_slice::<Value>(body).ok()?;
+
+                match json.pointer(pointer)? {
+                    Value::String(value) => Some(value.to_owned()),
+                    value => Some(value.to_string()),
+                }
+            }
+            Self::Header(name) => response
+                .headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_owned),
+            Self::Regex(regex) => {
+                let captures = regex.captures(body)?;
+                let value = captures.get(1).or_else(|| captures.get(0))?;
+
+                Some(String::from_utf8_lossy(value.as_bytes()).into_owned())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Text(String),
+    Variable(String),
+}
+
+// Text with `{{name}}` placeholders replaced by extracted variables.
+#[derive(Debug, Clone)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Option<Self> {
+        let mut segments = Vec::new();
+        let mut rest = template;
+
+        while let Some(start) = rest.find("{{") {
+            let end = rest[start..].find("}}")? + start;
+            let name = rest[start + 2..end].trim();
+
+            if name.is_empty() {
+                return None;
+            }
+            if start != 0 {
+                segments.push(Segment::Text(rest[..start].to_owned()));
+            }
+
+            segments.push(Segment::Variable(name.to_owned()));
+            rest = &rest[end + 2..];
+        }
+
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest.to_owned()));
+        }
+
+        Some(Self { segments })
+    }
+
+    fn get_variables(&self) -> impl Iterator<Item = &String> {
+        self.segments.iter().filter_map(|segment| match segment {
+            Segment::Variable(name) => Some(name),
+            Segment::Text(_) => None,
+        })
+    }
+
+    pub fn render(&self, variables: &HashMap<String, String>) -> String {
+        self.render_with(variables, |rendered, value, _| rendered.push_str(value))
+    }
+
+    // Extracted values can hold `/`, `?`, `&` or spaces. A value inside a
+    // path segment or query value is percent-encoded but for unreserved
+    // characters, so it stays that single component. A value filling the
+    // whole URL, whole path or whole query, like a link read from a
+    // response, keeps its delimiters and only characters never allowed in
+    // URLs are encoded.
+    pub fn render_url(&self, variables: &HashMap<String, String>) -> String {
+        self.render_with(variables, |rendered, value, is_last| {
+            let is_component = rendered.is_empty()
+                || Self::is_authority(rendered)
+                || (is_last && rendered.ends_with('?'));
+
+            for byte in value.bytes() {
+                let is_kept = match byte {
+                    b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => true,
+                    b'"' | b'<' | b'>' | b'\\' | b'^' | b'`' | b'{' | b'|' | b'}' => false,
+                    b'!'..=b'~' => is_component,
+                    _ => false,
+                };
+
+                if is_kept {
+                    rendered.push(byte as char);
+                } else {
+                    let _ = write!(rendered, "%{:02X}", byte);
+                }
+            }
+        })
+    }
+
+    // Whether URL ends right after its host, with no path yet.
+    fn is_authority(url: &str) -> bool {
+        match url.split_once("://") {
+            Some((scheme, authority)) => {
+                !scheme.is_empty()
+                    && scheme
+                        .bytes()
+                        .all(|byte| byte.is_ascii_alphanumeric() || b"+-.".contains(&byte))
+                    && !authority.contains(['/', '?', '#'])
+            }
+            None => false,
+        }
+    }
+
+    fn render_with<F>(&self, variables: &HashMap<String, String>, push_value: F) -> String
+    where
+        F: Fn(&mut String, &str, bool),
+    {
+        let mut rendered = String::new();
+
+        for (index, segment) in self.segments.iter().enumerate() {
+            match segment {
+                Segment::Text(text) => rendered.push_str(text),
+                Segment::Variable(name) => push_value(
+                    &mut rendered,
+                    variables.get(name).map_or("", String::as_str),
+                    index + 1 == self.segments.len(),
+                ),
+            }
+        }
+
+        rendered
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub name: String,
+    pub method: Method,
+    pub url: String,
+    url_template: Template,
+    headers: Vec<(HeaderName, Template)>,
+    payload: Option<Template>,
+    extractors: Vec<(String, Extractor)>,
+    assertions: Vec<Assertion>,
+}
+
+pub struct StepRequest {
+    pub method: Method,
+    pub url: String,
+    pub headers: HeaderMap,
+    pub payload: Option<String>,
+}
+
+// Ordered requests every connection walks through as a virtual user, values
+// extracted from a response feed requests of later steps.
+#[derive(Debug, Clone)]
+pub struct Scenario {
+    steps: Vec<Step>,
+}
+
+impl Scenario {
+    // Relative step URLs starting with `/` are resolved against target, and
+    // every variable has to be extracted by an earlier step.
+    pub fn parse(steps: Vec<StepConfig>, target: &str, method: &Method) -> AppResult<Self> {
+        let mut parsed_steps = Vec::with_capacity(steps.len());
+        let mut variables = HashSet::new();
+
+        for step in steps {
+            let method = match step.method {
+                Some(method) => App::parse_method(method)?,
+                None => method.clone(),
+            };
+            let url = match step.url {
+                Some(url) if url.starts_with('/') => {
+                    format!("{}{}", target.trim_end_matches('/'), url)
+                }
+                Some(url) => url,
+                None => target.to_owned(),
+            };
+            let name = step
+                .name
+                .unwrap_or_else(|| format!("{} {}", method.as_str(), url));
+            let invalid = |message: String| Config::invalid(format!("step {}: {}", name, message));
+
+            let url_template = match Template::parse(&url) {
+                Some(template) => template,
+                None => return Err(invalid("invalid url template.".to_owned())),
+            };
+            let mut headers = Vec::with_capacity(step.headers.len());
+
+            for (header_name, value) in &step.headers {
+                let header_name = match header_name.parse() {
+                    Ok(header_name) => header_name,
+                    Err(_) => return Err(invalid(format!("invalid header name {}.", header_name))),
+                };
+
+                match Template::parse(value) {
+                    Some(template) => headers.push((header_name, template)),
+                    None => return Err(invalid(format!("invalid header template {}.", value))),
+                }
+            }
+
+            let payload = match step.payload {
+                Some(payload) => match Template::parse(&payload) {
+                    Some(template) => Some(template),
+                    None => return Err(invalid("invalid body template.".to_owned())),
+                },
+                None => None,
+            };
+
+            for variable in url_template
+                .get_variables()
+                .chain(headers.iter().flat_map(|(_, value)| value.get_variables()))
+                .chain(payload.iter().flat_map(Template::get_variables))
+            {
+                if !variables.contains(variable) {
+                    return Err(invalid(format!(
+                        "variable {} isn't extracted by an earlier step.",
+                        variable
+                    )));
+                }
+            }
+
+            let extractors = Self::parse_extractors(&step.extract)
+                .map_err(|extractor| invalid(format!("invalid extractor {}, extractors must be provided in the following formats: json:<PATH>, header:<NAME> or regex:<REGEX>", extractor)))?;
+            let mut assertions = Vec::with_capacity(step.assertions.len());
+
+            for assertion in &step.assertions {
+                assertions.push(Assertion::parse(assertion)?);
+            }
+
+            variables.extend(extractors.iter().map(|(variable, _)| variable.to_owned()));
+
+            parsed_steps.push(Step {
+                name,
+                method,
+                url,
+                url_template,
+                headers,
+                payload,
+                extractors,
+                assertions,
+            });
+        }
+
+        Ok(Self {
+            steps: parsed_steps,
+        })
+    }
+
+    fn parse_extractors(
+        extractors: &BTreeMap<String, String>,
+    ) -> Result<Vec<(String, Extractor)>, String> {
+        let mut parsed_extractors = Vec::with_capacity(extractors.len());
+
+        for (variable, extractor) in extractors {
+            match Extractor::parse(extractor) {
+                Some(parsed_extractor) => {
+                    parsed_extractors.push((variable.to_owned(), parsed_extractor))
+                }
+                None => return Err(extractor.to_owned()),
+            }
+        }
+
+        Ok(parsed_extractors)
+    }
+
+    pub fn get_steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    pub fn needs_body(&self) -> bool {
+        self.steps.iter().any(|step| {
+            step.extractors
+                .iter()
+                .any(|(_, extractor)| extractor.needs_body())
+                || step.assertions.iter().any(Assertion::needs_body)
+        })
+    }
+}
+
+// Walks scenario steps in order for one connection. A step that fails, fails
+// an assertion or can't extract its variables starts journey over, since
+// later steps would go out without values they depend on.
+pub struct VirtualUser<'a> {
+    scenario: &'a Scenario,
+    step: usize,
+    variables: HashMap<String, String>,
+}
+
+impl<'a> VirtualUser<'a> {
+    pub fn new(scenario: &'a Scenario) -> Self {
+        Self {
+            scenario,
+            step: 0,
+            variables: HashMap::new(),
+        }
+    }
+
+    // Header values that turn invalid once rendered are left out, target
+    // decides what a request without them gets.
+    pub fn get_request(&self) -> StepRequest {
+        let step = &self.scenario.steps[self.step];
+        let mut headers = HeaderMap::with_capacity(step.headers.len());
+
+        for (name, value) in &step.headers {
+            if let Ok(value) = HeaderValue::from_str(&value.render(&self.variables)) {
+                headers.insert(name.clone(), value);
+            }
+        }
+
+        StepRequest {
+            method: step.method.clone(),
+            url: step.url_template.render_url(&self.variables),
+            headers,
+            payload: step
+                .payload
+                .as_ref()
+                .map(|payload| payload.render(&self.variables)),
+        }
+    }
+
+    pub fn complete(
+        &mut self,
+        request: &StepRequest,
+        response: Option<&HttpResponse>,
+        sample: &mut Sample,
+    ) {
+        let step = &self.scenario.steps[self.step];
+
+        sample.step = Some(self.step);
+        sample.sent_bytes = sample.sent_bytes.map(|_| {
+            request
+                .payload
+                .as_ref()
+                .map_or(0, |payload| payload.len() as u64)
+        });
+
+        if let Some(response) = response {
+            if !Assertion::check_all(&step.assertions, response, sample.response_time) {
+                sample.status_code_category = StatusCodeCategory::AssertionFailed;
+            }
+
+            for (variable, extractor) in &step.extractors {
+                match extractor.extract(response) {
+                    Some(value) => {
+                        self.variables.insert(variable.to_owned(), value);
+                    }
+                    None => sample.status_code_category = StatusCodeCategory::AssertionFailed,
+                }
+            }
+        }
+
+        self.step = match sample.status_code_category {
+            StatusCodeCategory::Failed | StatusCodeCategory::AssertionFailed => 0,
+            _ => (self.step + 1) % self.scenario.steps.len(),
+        };
+
+        if self.step == 0 {
+            self.variables.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use reqwest::StatusCode;
+
+    use super::*;
+
+    fn step(url: &str, extract: &[(&str, &str)]) -> StepConfig {
+        StepConfig {
+            name: None,
+            method: None,
+            url: Some(url.to_owned()),
+            headers: BTreeMap::new(),
+            payload: None,
+            extract: extract
+                .iter()
+                .map(|(variable, extractor)| (variable.to_string(), extractor.to_string()))
+                .collect(),
+            assertions: Vec::new(),
+        }
+    }
+
+    fn scenario() -> Scenario {
+        Scenario::parse(
+            vec![
+                step("/items", &[("id", "json:$.id")]),
+                step("/items/{{id}}?q={{id}}", &[]),
+            ],
+            "http://localhost:5500/",
+            &Method::GET,
+        )
+        .unwrap()
+    }
+
+    fn response(body: &str) -> HttpResponse {
+        HttpResponse {
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            body_size: body.len() as u64,
+            body: Some(body.as_bytes().to_vec()),
+        }
+    }
+
+    fn sample(status_code_category: StatusCodeCategory) -> Sample {
+        Sample {
+            status_code_category,
+            status_code: None,
+            response_time: Duration::from_millis(1),
+            ..Sample::default()
+        }
+    }
+
+    fn complete(
+        virtual_user: &mut VirtualUser,
+        response: Option<&HttpResponse>,
+        status_code_category: StatusCodeCategory,
+    ) -> Sample {
+        let request = virtual_user.get_request();
+        let mut sample = sample(status_code_category);
+
+        virtual_user.complete(&request, response, &mut sample);
+
+        sample
+    }
+
+    #[test]
+    fn template_parses_text_and_variables() {
+        let template = Template::parse("/users/{{ id }}/posts/{{post}}").unwrap();
+        let variables = HashMap::from([
+            ("id".to_owned(), "7".to_owned()),
+            ("post".to_owned(), "9".to_owned()),
+        ]);
+
+        assert_eq!(template.get_variables().count(), 2);
+        assert_eq!(template.render(&variables), "/users/7/posts/9");
+        assert_eq!(
+            Template::parse("no variables")
+                .unwrap()
+                .get_variables()
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn template_rejects_unclosed_and_empty_variables() {
+        assert!(Template::parse("/users/{{id").is_none());
+        assert!(Template::parse("/users/{{id}}/{{").is_none());
+        assert!(Template::parse("/users/{{}}").is_none());
+        assert!(Template::parse("/users/{{  }}").is_none());
+    }
+
+    #[test]
+    fn url_values_are_percent_encoded() {
+        let template = Template::parse("http://localhost/search?q={{query}}").unwrap();
+        let variables = HashMap::from([("query".to_owned(), "a b/c?d=1&e~_.-".to_owned())]);
+
+        assert_eq!(
+            template.render_url(&variables),
+            "http://localhost/search?q=a%20b%2Fc%3Fd%3D1%26e~_.-"
+        );
+        assert_eq!(
+            template.render(&variables),
+            "http://localhost/search?q=a b/c?d=1&e~_.-"
+        );
+    }
+
+    #[test]
+    fn url_values_filling_whole_components_keep_delimiters() {
+        let variables = HashMap::from([
+            ("url".to_owned(), "http://localhost/items?page=2".to_owned()),
+            ("path".to_owned(), "/items/a%20b?page=2&size=10".to_owned()),
+            ("query".to_owned(), "page=2&size=10".to_owned()),
+        ]);
+        let render_url = |template| Template::parse(template).unwrap().render_url(&variables);
+
+        assert_eq!(render_url("{{url}}"), "http://localhost/items?page=2");
+        assert_eq!(
+            render_url("http://localhost:5500{{path}}"),
+            "http://localhost:5500/items/a%20b?page=2&size=10"
+        );
+        assert_eq!(
+            render_url("http://localhost/items?{{query}}"),
+            "http://localhost/items?page=2&size=10"
+        );
+        assert_eq!(
+            render_url("http://localhost/items?{{query}}&size=5"),
+            "http://localhost/items?page%3D2%26size%3D10&size=5"
+        );
+        assert_eq!(
+            render_url("http://localhost/{{path}}"),
+            "http://localhost/%2Fitems%2Fa%2520b%3Fpage%3D2%26size%3D10"
+        );
+    }
+
+    #[test]
+    fn virtual_user_follows_extracted_location() {
+        let scenario = Scenario::parse(
+            vec![
+                step("/items", &[("next", "header:location")]),
+                step("{{next}}", &[]),
+            ],
+            "http://localhost:5500/",
+            &Method::GET,
+        )
+        .unwrap();
+        let mut virtual_user = VirtualUser::new(&scenario);
+        let mut response = response("");
+
+        response.headers.insert(
+            "location",
+            HeaderValue::from_static("http://localhost:5500/items/42?view=full&lang=en"),
+        );
+        complete(
+            &mut virtual_user,
+            Some(&response),
+            StatusCodeCategory::Success,
+        );
+
+        assert_eq!(
+            virtual_user.get_request().url,
+            "http://localhost:5500/items/42?view=full&lang=en"
+        );
+    }
+
+    #[test]
+    fn virtual_user_walks_steps_and_wraps_around() {
+        let scenario = scenario();
+        let mut virtual_user = VirtualUser::new(&scenario);
+
+        let sample = complete(
+            &mut virtual_user,
+            Some(&response(r#"{"id":"a/b"}"#)),
+            StatusCodeCategory::Success,
+        );
+
+        assert_eq!(sample.step, Some(0));
+        assert_eq!(virtual_user.step, 1);
+        assert_eq!(
+            virtual_user.get_request().url,
+            "http://localhost:5500/items/a%2Fb?q=a%2Fb"
+        );
+
+        let sample = complete(
+            &mut virtual_user,
+            Some(&response("{}")),
+            StatusCodeCategory::Success,
+        );
+
+        assert_eq!(sample.step, Some(1));
+        assert_eq!(virtual_user.step, 0);
+        assert!(virtual_user.variables.is_empty());
+    }
+
+    #[test]
+    fn virtual_user_restarts_after_failed_step() {
+        let scenario = scenario();
+        let mut virtual_user = VirtualUser::new(&scenario);
+
+        complete(
+            &mut virtual_user,
+            Some(&response(r#"{"id":42}"#)),
+            StatusCodeCategory::Success,
+        );
+
+        assert_eq!(virtual_user.variables["id"], "42");
+
+        complete(&mut virtual_user, None, StatusCodeCategory::Failed);
+
+        assert_eq!(virtual_user.step, 0);
+        assert!(virtual_user.variables.is_empty());
+    }
+
+    #[test]
+    fn virtual_user_restarts_when_extraction_fails() {
+        let scenario = scenario();
+        let mut virtual_user = VirtualUser::new(&scenario);
+
+        let sample = complete(
+            &mut virtual_user,
+            Some(&response(r#"{"name":"missing id"}"#)),
+            StatusCodeCategory::Success,
+        );
+
+        assert!(matches!(
+            sample.status_code_category,
+            StatusCodeCategory::AssertionFailed
+        ));
+        assert_eq!(virtual_user.step, 0);
+    }
+
+    #[test]
+    fn virtual_user_counts_payload_of_each_step() {
+        let mut steps = vec![
+            step("/login", &[]),
+            step("/items", &[]),
+            step("/logout", &[]),
+        ];
+
+        steps[0].payload = Some(r#"{"user":"cicadas"}"#.to_owned());
+        steps[1].payload = Some("{}".to_owned());
+
+        let scenario = Scenario::parse(steps, "http://localhost:5500/", &Method::POST).unwrap();
+        let mut virtual_user = VirtualUser::new(&scenario);
+        let response = response("");
+        let mut sent_bytes = Vec::new();
+
+        for _ in 0..3 {
+            let request = virtual_user.get_request();
+            let mut sample = Sample {
+                sent_bytes: Some(0),
+                ..sample(StatusCodeCategory::Success)
+            };
+
+            virtual_user.complete(&request, Some(&response), &mut sample);
+            sent_bytes.push((sample.step, sample.sent_bytes));
+        }
+
+        assert_eq!(
+            sent_bytes,
+            [(Some(0), Some(18)), (Some(1), Some(2)), (Some(2), Some(0))]
+        );
+
+        // Requests without a response send nothing counted.
+        let request = virtual_user.get_request();
+        let mut sample = sample(StatusCodeCategory::Failed);
+
+        virtual_user.complete(&request, None, &mut sample);
+
+        assert_eq!(sample.sent_bytes, None);
+    }
+}
@@ -0,0 +1,386 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{app::Arguments, profile::Profile};
+
+// Shortest time a connection waits for a load profile to activate it, so
+// rounding errors on ramp boundaries can't turn into busy loops.
+const MIN_ACTIVATION_WAIT: Duration = Duration::from_millis(1);
+
+pub enum Slot {
+    Send(Instant, Option<usize>),
+    WarmUp(Instant),
+    Wait(Instant),
+    Finish,
+}
+
+pub struct Scheduler {
+    warmup_start_time: Instant,
+    start_time: Instant,
+    duration: Option<Duration>,
+    requests: Option<u64>,
+    rate: Option<u64>,
+    profile: Option<Profile>,
+    tickets: AtomicU64,
+    warmup_tickets: AtomicU64,
+    stopped: AtomicBool,
+    stop_time: OnceLock<Instant>,
+}
+
+impl Scheduler {
+    pub fn from_arguments(arguments: &Arguments) -> Self {
+        let warmup_start_time = Instant::now();
+
+        Self {
+            warmup_start_time,
+            start_time: warmup_start_time + Duration::from_secs(arguments.warmup),
+            duration: arguments.duration.map(Duration::from_secs),
+            requests: arguments.requests,
+            rate: arguments.rate,
+            profile: arguments.profile.clone(),
+            tickets: AtomicU64::new(0),
+            warmup_tickets: AtomicU64::new(0),
+            stopped: AtomicBool::new(false),
+            stop_time: OnceLock::new(),
+        }
+    }
+
+    // Stops load test early, connections finish their in flight request and
+    // get `Slot::Finish` on next call.
+    pub fn stop(&self) {
+        let _ = self.stop_time.set(Instant::now());
+
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+
+    // Warm-up period is excluded from elapsed time since its requests aren't
+    // part of results.
+    pub fn get_elapsed_time(&self) -> Duration {
+        Instant::now().saturating_duration_since(self.start_time)
+    }
+
+    // How long load test ran for, rates are computed over it. Runs end when
+    // they are stopped or their duration is up, not when connections have
+    // finished requests still in flight by then, which can take up to the
+    // request timeout. A requests count has no scheduled end, so its run
+    // lasts until the last response came back.
+    pub fn get_run_time(&self) -> Duration {
+        self.get_run_time_at(Instant::now())
+    }
+
+    fn get_run_time_at(&self, now: Instant) -> Duration {
+        let mut end_time = self
+            .stop_time
+            .get()
+            .map_or(now, |stop_time| now.min(*stop_time));
+
+        if let Some(duration) = self.duration {
+            end_time = end_time.min(self.start_time + duration);
+        }
+
+        end_time.saturating_duration_since(self.start_time)
+    }
+
+    pub fn get_warmup_remaining_time(&self) -> Duration {
+        self.start_time.saturating_duration_since(Instant::now())
+    }
+
+    fn get_rate_offset(rate: u64, ticket: u64) -> Duration {
+        Duration::from_nanos((ticket as u128 * 1_000_000_000 / rate as u128) as u64)
+    }
+
+    // During warm-up connections send requests back to back, or at the same
+    // constant rate, until load test actually starts. With a load profile
+    // only the connections its first stage starts with warm up, at least one,
+    // so warm-up doesn't put more load on target than load test starts with.
+    fn next_warmup(&self, worker: usize, now: Instant) -> Slot {
+        if let Some(profile) = &self.profile {
+            if worker >= profile.get_connections(Duration::ZERO).max(1) {
+                return Slot::Wait(self.start_time);
+            }
+        }
+
+        let intended_time = match self.rate {
+            Some(rate) => {
+                let ticket = self.warmup_tickets.fetch_add(1, Ordering::Relaxed);
+
+                self.warmup_start_time + Self::get_rate_offset(rate, ticket)
+            }
+            None => now,
+        };
+
+        if intended_time >= self.start_time {
+            return Slot::Wait(self.start_time);
+        }
+
+        Slot::WarmUp(intended_time)
+    }
+
+    fn is_finished(&self, elapsed: Duration) -> bool {
+        match self.duration {
+            Some(duration) => elapsed >= duration,
+            None => false,
+        }
+    }
+
+    // Returns what connection number `worker` should do next: send a request
+    // at the time it was intended to be sent at and within which load profile
+    // stage, send a warm-up request, wait until load profile activates it, or
+    // finish. In rate mode every request takes the next slot of a global
+    // schedule, so requests arrive at a fixed rate no matter which connection
+    // sends them or how fast responses come back. With a requests count every
+    // request takes a ticket, so exactly that many requests are sent across
+    // all connections.
+    pub fn next(&self, worker: usize) -> Slot {
+        self.next_at(worker, Instant::now())
+    }
+
+    fn next_at(&self, worker: usize, now: Instant) -> Slot {
+        if self.is_stopped() {
+            return Slot::Finish;
+        }
+
+        if now < self.start_time {
+            return self.next_warmup(worker, now);
+        }
+
+        let elapsed = now - self.start_time;
+
+        if self.is_finished(elapsed) {
+            return Slot::Finish;
+        }
+
+        if let Some(profile) = &self.profile {
+            if worker >= profile.get_connections(elapsed) {
+                return match profile.get_activation(worker, elapsed) {
+                    Some(activation) => {
+                        Slot::Wait((self.start_time + activation).max(now + MIN_ACTIVATION_WAIT))
+                    }
+                    None => Slot::Finish,
+                };
+            }
+        }
+
+        let ticket = match (self.rate, self.requests) {
+            (None, None) => 0,
+            _ => self.tickets.fetch_add(1, Ordering::Relaxed),
+        };
+
+        if let Some(requests) = self.requests {
+            if ticket >= requests {
+                return Slot::Finish;
+            }
+        }
+
+        let offset = match self.rate {
+            Some(rate) => Self::get_rate_offset(rate, ticket),
+            None => elapsed,
+        };
+
+        if self.is_finished(offset) {
+            return Slot::Finish;
+        }
+
+        let stage = match &self.profile {
+            Some(profile) => profile.get_stage_index(offset),
+            None => None,
+        };
+
+        Slot::Send(self.start_time + offset, stage)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Method;
+
+    use super::*;
+
+    fn scheduler<F>(configure: F) -> Scheduler
+    where
+        F: FnOnce(&mut Arguments),
+    {
+        let mut arguments = Arguments::new("http://localhost:5500/".to_owned(), Method::GET);
+
+        configure(&mut arguments);
+
+        Scheduler::from_arguments(&arguments)
+    }
+
+    fn millis(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn rate_mode_shares_one_schedule_across_workers() {
+        let scheduler = scheduler(|arguments| {
+            arguments.duration = Some(1);
+            arguments.rate = Some(4);
+        });
+        let start_time = scheduler.start_time;
+
+        // Intended time only depends on ticket order, not on which worker
+        // takes it or when it asks.
+        for (worker, now, intended_time) in [
+            (0, millis(0), millis(0)),
+            (3, millis(0), millis(250)),
+            (0, millis(600), millis(500)),
+            (1, millis(601), millis(750)),
+        ] {
+            assert!(matches!(
+                scheduler.next_at(worker, start_time + now),
+                Slot::Send(time, None) if time == start_time + intended_time
+            ));
+        }
+
+        assert!(matches!(
+            scheduler.next_at(2, start_time + millis(700)),
+            Slot::Finish
+        ));
+    }
+
+    #[test]
+    fn requests_count_is_shared_across_workers() {
+        let scheduler = scheduler(|arguments| {
+            arguments.requests = Some(5);
+            arguments.connections = 3;
+        });
+        let now = scheduler.start_time + millis(10);
+        let mut sent = 0;
+
+        for turn in 0..9 {
+            if let Slot::Send(intended_time, _) = scheduler.next_at(turn % 3, now) {
+                assert_eq!(intended_time, now);
+                sent += 1;
+            }
+        }
+
+        assert_eq!(sent, 5);
+        assert!((0..3).all(|worker| matches!(scheduler.next_at(worker, now), Slot::Finish)));
+    }
+
+    #[test]
+    fn warmup_slots_come_before_measurement() {
+        let scheduler = scheduler(|arguments| {
+            arguments.duration = Some(10);
+            arguments.warmup = 1;
+            arguments.rate = Some(2);
+        });
+        let (warmup_start_time, start_time) = (scheduler.warmup_start_time, scheduler.start_time);
+
+        assert_eq!(start_time - warmup_start_time, millis(1000));
+        assert!(matches!(
+            scheduler.next_at(0, warmup_start_time),
+            Slot::WarmUp(time) if time == warmup_start_time
+        ));
+        assert!(matches!(
+            scheduler.next_at(1, warmup_start_time + millis(100)),
+            Slot::WarmUp(time) if time == warmup_start_time + millis(500)
+        ));
+        // Warm-up schedule ran out, connections wait for load test to start.
+        assert!(matches!(
+            scheduler.next_at(0, warmup_start_time + millis(600)),
+            Slot::Wait(time) if time == start_time
+        ));
+        // Measurement schedule starts over from its first ticket.
+        assert!(matches!(
+            scheduler.next_at(0, start_time),
+            Slot::Send(time, None) if time == start_time
+        ));
+    }
+
+    #[test]
+    fn warmup_only_uses_connections_profile_starts_with() {
+        let scheduler = scheduler(|arguments| {
+            let profile = Profile::parse("hold:5:2,hold:5:4").unwrap();
+
+            arguments.duration = Some(profile.get_duration());
+            arguments.connections = profile.get_max_connections();
+            arguments.profile = Some(profile);
+            arguments.warmup = 1;
+        });
+        let (warmup_start_time, start_time) = (scheduler.warmup_start_time, scheduler.start_time);
+
+        assert!(matches!(
+            scheduler.next_at(1, warmup_start_time),
+            Slot::WarmUp(_)
+        ));
+        assert!(matches!(
+            scheduler.next_at(2, warmup_start_time),
+            Slot::Wait(time) if time == start_time
+        ));
+        assert!(matches!(
+            scheduler.next_at(2, start_time),
+            Slot::Wait(time) if time == start_time + Duration::from_secs(5)
+        ));
+        assert!(matches!(
+            scheduler.next_at(2, start_time + Duration::from_secs(5)),
+            Slot::Send(_, Some(1))
+        ));
+    }
+
+    #[test]
+    fn warmup_of_ramp_from_zero_uses_one_connection() {
+        let scheduler = scheduler(|arguments| {
+            arguments.profile = Some(Profile::parse("ramp:10:4").unwrap());
+            arguments.warmup = 1;
+        });
+
+        assert!(matches!(
+            scheduler.next_at(0, scheduler.warmup_start_time),
+            Slot::WarmUp(_)
+        ));
+        assert!(matches!(
+            scheduler.next_at(1, scheduler.warmup_start_time),
+            Slot::Wait(_)
+        ));
+    }
+
+    #[test]
+    fn stopped_scheduler_finishes() {
+        let scheduler = scheduler(|arguments| arguments.duration = Some(10));
+
+        scheduler.stop();
+
+        assert!(matches!(scheduler.next(0), Slot::Finish));
+    }
+
+    #[test]
+    fn run_time_leaves_out_draining_after_duration() {
+        let scheduler = scheduler(|arguments| arguments.duration = Some(1));
+        let start_time = scheduler.start_time;
+
+        assert_eq!(
+            scheduler.get_run_time_at(start_time + millis(400)),
+            millis(400)
+        );
+        assert_eq!(
+            scheduler.get_run_time_at(start_time + millis(4000)),
+            millis(1000)
+        );
+    }
+
+    #[test]
+    fn run_time_ends_when_stopped() {
+        let scheduler = scheduler(|arguments| arguments.requests = Some(5));
+
+        scheduler.stop();
+
+        let stop_time = *scheduler.stop_time.get().unwrap();
+        let run_time = stop_time.saturating_duration_since(scheduler.start_time);
+
+        assert_eq!(
+            scheduler.get_run_time_at(stop_time + millis(4000)),
+            run_time
+        );
+    }
+}
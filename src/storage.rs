@@ -1,37 +1,500 @@
 use std::{
-    collections::HashMap,
-    sync::{Mutex, MutexGuard},
+    collections::{btree_map::Entry, BTreeMap, HashMap},
+    mem,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex, MutexGuard,
+    },
     time::Duration,
 };
 
+use hdrhistogram::Histogram;
+
 use crate::{
+    app::Arguments,
     errors::{AppResult, ErrorType},
-    http::StatusCodeCategory,
+    http::{FailureKind, Phase, StatusCodeCategory, STATUS_CODE_CATEGORIES},
+    profile::Profile,
+    recorder::{Failures, Recorder},
 };
 
+// Lowest latency distribution bucket in microseconds, next buckets double in
+// size so a whole hour fits in a few dozen buckets.
+const DISTRIBUTION_FIRST_BUCKET: u64 = 100;
+
+// Latest seconds of load test kept as full histograms while recorders are
+// still merging samples into them, older seconds are reduced to their stats.
+const OPEN_SECONDS: u64 = 5;
+
+type StatusCodeHistograms = HashMap<(StatusCodeCategory, u16), Histogram<u64>>;
+
 pub struct Storage {
-    response_times: HashMap<StatusCodeCategory, Mutex<Vec<u128>>>,
+    significant_figures: u8,
+    elapsed_time: Mutex<Duration>,
+    interrupted: AtomicBool,
+    response_times: ResponseTimes,
+    corrected_response_times: ResponseTimes,
+    stage_response_times: Vec<ResponseTimes>,
+    step_response_times: Vec<ResponseTimes>,
+    time_series: Mutex<TimeSeries>,
+    warmup_response_times: ResponseTimes,
+    live_response_times: ResponseTimes,
+    status_code_response_times: StatusCodeResponseTimes,
+    failures: Mutex<HashMap<FailureKind, Failures>>,
+    phase_response_times: PhaseResponseTimes,
+    sent_bytes: AtomicU64,
+    received_bytes: AtomicU64,
+    worker_requests: Mutex<Vec<u64>>,
+    profile: Option<Profile>,
 }
 
 impl Storage {
-    pub fn new() -> Self {
-        let mut response_times = HashMap::new();
+    pub fn from_arguments(arguments: &Arguments) -> AppResult<Self> {
+        let stages = match &arguments.profile {
+            Some(profile) => profile.get_stages().len(),
+            None => 0,
+        };
+        let mut stage_response_times = Vec::with_capacity(stages);
 
-        response_times.insert(StatusCodeCategory::Success, Mutex::new(Vec::new()));
-        response_times.insert(StatusCodeCategory::Redirection, Mutex::new(Vec::new()));
-        response_times.insert(StatusCodeCategory::ClientError, Mutex::new(Vec::new()));
-        response_times.insert(StatusCodeCategory::ServerError, Mutex::new(Vec::new()));
-        response_times.insert(StatusCodeCategory::Informational, Mutex::new(Vec::new()));
-        response_times.insert(StatusCodeCategory::Failed, Mutex::new(Vec::new()));
+        for _ in 0..stages {
+            stage_response_times.push(ResponseTimes::new(arguments.significant_figures)?);
+        }
 
-        Self { response_times }
+        let steps = match &arguments.scenario {
+            Some(scenario) => scenario.get_steps().len(),
+            None => 0,
+        };
+        let mut step_response_times = Vec::with_capacity(steps);
+
+        for _ in 0..steps {
+            step_response_times.push(ResponseTimes::new(arguments.significant_figures)?);
+        }
+
+        Ok(Self {
+            significant_figures: arguments.significant_figures,
+            elapsed_time: Mutex::new(Duration::ZERO),
+            interrupted: AtomicBool::new(false),
+            response_times: ResponseTimes::new(arguments.significant_figures)?,
+            corrected_response_times: ResponseTimes::new(arguments.significant_figures)?,
+            stage_response_times,
+            step_response_times,
+            time_series: Mutex::new(TimeSeries::default()),
+            warmup_response_times: ResponseTimes::new(arguments.significant_figures)?,
+            live_response_times: ResponseTimes::new(arguments.significant_figures)?,
+            status_code_response_times: StatusCodeResponseTimes::new(),
+            failures: Mutex::new(HashMap::new()),
+            phase_response_times: PhaseResponseTimes::new(arguments.significant_figures)?,
+            sent_bytes: AtomicU64::new(0),
+            received_bytes: AtomicU64::new(0),
+            worker_requests: Mutex::new(vec![0; arguments.connections]),
+            profile: arguments.profile.clone(),
+        })
+    }
+
+    pub fn merge(&self, recorder: &Recorder) -> AppResult<()> {
+        self.response_times.merge(recorder.get_response_times())?;
+        self.corrected_response_times
+            .merge(recorder.get_corrected_response_times())?;
+        self.warmup_response_times
+            .merge(recorder.get_warmup_response_times())?;
+        self.live_response_times
+            .merge(recorder.get_response_times())?;
+        self.live_response_times
+            .merge(recorder.get_warmup_response_times())?;
+        self.status_code_response_times
+            .merge(recorder.get_status_code_response_times())?;
+
+        self.phase_response_times
+            .merge(recorder.get_phase_response_times())?;
+        self.sent_bytes
+            .fetch_add(recorder.get_sent_bytes(), Ordering::Relaxed);
+        self.received_bytes
+            .fetch_add(recorder.get_received_bytes(), Ordering::Relaxed);
+
+        match self.worker_requests.lock() {
+            Ok(mut worker_requests) => {
+                for (worker, requests) in recorder.get_worker_requests().iter().enumerate() {
+                    if worker_requests.len() <= worker {
+                        worker_requests.resize(worker + 1, 0);
+                    }
+
+                    worker_requests[worker] += requests;
+                }
+            }
+            Err(_) => return Err(ErrorType::InvalidError),
+        }
+
+        match self.failures.lock() {
+            Ok(mut failures) => {
+                for (failure_kind, recorder_failures) in recorder.get_failures() {
+                    let failures = failures.entry(failure_kind).or_default();
+
+                    failures.count += recorder_failures.count;
+                    failures.add_messages(recorder_failures.messages.iter());
+                }
+            }
+            Err(_) => return Err(ErrorType::InvalidError),
+        }
+
+        for (stage, response_times) in recorder.get_stage_response_times().enumerate() {
+            match self.stage_response_times.get(stage) {
+                Some(stage_response_times) => stage_response_times.merge(response_times)?,
+                None => return Err(ErrorType::InvalidError),
+            }
+        }
+
+        for (step, response_times) in recorder.get_step_response_times().enumerate() {
+            match self.step_response_times.get(step) {
+                Some(step_response_times) => step_response_times.merge(response_times)?,
+                None => return Err(ErrorType::InvalidError),
+            }
+        }
+
+        match self.time_series.lock() {
+            Ok(mut time_series) => time_series.merge(
+                recorder.get_second_response_times(),
+                self.significant_figures,
+            )?,
+            Err(_) => return Err(ErrorType::InvalidError),
+        }
+
+        Ok(())
+    }
+
+    pub fn set_elapsed_time(&self, elapsed_time: Duration) -> AppResult<()> {
+        match self.elapsed_time.lock() {
+            Ok(mut guard) => *guard = elapsed_time,
+            Err(_) => return Err(ErrorType::InvalidError),
+        }
+
+        Ok(())
+    }
+
+    pub fn get_elapsed_time(&self) -> AppResult<Duration> {
+        match self.elapsed_time.lock() {
+            Ok(guard) => Ok(*guard),
+            Err(_) => Err(ErrorType::InvalidError),
+        }
+    }
+
+    pub fn set_interrupted(&self, interrupted: bool) {
+        self.interrupted.store(interrupted, Ordering::Relaxed);
+    }
+
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::Relaxed)
+    }
+
+    pub fn get_response_times(&self) -> &ResponseTimes {
+        &self.response_times
+    }
+
+    // Response times measured from the time requests were intended to be sent
+    // at in rate mode, so stalls on target can't hide requests queued behind.
+    pub fn get_corrected_response_times(&self) -> &ResponseTimes {
+        &self.corrected_response_times
+    }
+
+    pub fn get_stage_response_times(&self) -> &[ResponseTimes] {
+        &self.stage_response_times
+    }
+
+    pub fn get_step_response_times(&self) -> &[ResponseTimes] {
+        &self.step_response_times
+    }
+
+    // Requests count and latency per second of load test, so throughput and
+    // latency can be followed over the course of the run.
+    pub fn get_time_series(&self) -> AppResult<Vec<SecondStats>> {
+        match self.time_series.lock() {
+            Ok(time_series) => time_series.get_seconds(),
+            Err(_) => Err(ErrorType::InvalidError),
+        }
+    }
+
+    pub fn get_warmup_response_times(&self) -> &ResponseTimes {
+        &self.warmup_response_times
+    }
+
+    pub fn get_status_code_response_times(&self) -> &StatusCodeResponseTimes {
+        &self.status_code_response_times
+    }
+
+    // Zero when there is nothing to divide by, like a load test stopped
+    // before any time passed or a run without responses.
+    pub fn get_rate(value: f64, per: f64) -> f64 {
+        if per == 0.0 {
+            0.0
+        } else {
+            value / per
+        }
+    }
+
+    // Uses real elapsed time, load test can be interrupted or stop early
+    // after sending all requests.
+    pub fn get_per_second(&self, value: f64) -> AppResult<f64> {
+        Ok(Self::get_rate(
+            value,
+            self.get_elapsed_time()?.as_secs_f64(),
+        ))
+    }
+
+    pub fn get_requests_per_second(&self, requests_count: usize) -> AppResult<f64> {
+        self.get_per_second(requests_count as f64)
+    }
+
+    // Spread of requests count over every full second of load test, last
+    // second is left out when load test stopped in the middle of it.
+    pub fn get_second_rate_stats(&self) -> AppResult<Option<RateStats>> {
+        let full_seconds = self.get_elapsed_time()?.as_secs() as usize;
+        let time_series = self.get_time_series()?;
+        let mut rates = Vec::with_capacity(full_seconds);
+
+        for second in 0..full_seconds {
+            rates.push(match time_series.get(second) {
+                Some(second_stats) => second_stats.total.requests as f64,
+                None => 0.0,
+            });
+        }
+
+        Ok(RateStats::from_rates(&rates))
+    }
+
+    // Spread of requests per second sent by each connection, over the time
+    // it was active. Load profiles activate connections for different spans,
+    // connections never activated before load test ended are left out.
+    pub fn get_worker_rate_stats(&self) -> AppResult<Option<RateStats>> {
+        let worker_requests = match self.worker_requests.lock() {
+            Ok(worker_requests) => worker_requests.clone(),
+            Err(_) => return Err(ErrorType::InvalidError),
+        };
+        let elapsed_time = self.get_elapsed_time()?;
+        let mut rates = Vec::with_capacity(worker_requests.len());
+
+        for (worker, requests) in worker_requests.into_iter().enumerate() {
+            let active_time = match &self.profile {
+                Some(profile) => profile.get_active_time(worker, elapsed_time),
+                None => elapsed_time,
+            };
+
+            if !active_time.is_zero() {
+                rates.push(Self::get_rate(requests as f64, active_time.as_secs_f64()));
+            }
+        }
+
+        Ok(RateStats::from_rates(&rates))
+    }
+
+    // Request and response body bytes of requests that got a response,
+    // warm-up excluded.
+    pub fn get_sent_bytes(&self) -> u64 {
+        self.sent_bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn get_received_bytes(&self) -> u64 {
+        self.received_bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn get_phase_response_times(&self) -> &PhaseResponseTimes {
+        &self.phase_response_times
+    }
+
+    // Failed requests per failure kind with a few sample error messages,
+    // ordered by kind.
+    pub fn get_failures(&self) -> AppResult<Vec<(FailureKind, Failures)>> {
+        let failures = match self.failures.lock() {
+            Ok(failures) => failures,
+            Err(_) => return Err(ErrorType::InvalidError),
+        };
+
+        Ok(FailureKind::all()
+            .into_iter()
+            .filter_map(|failure_kind| {
+                failures
+                    .get(&failure_kind)
+                    .map(|failures| (failure_kind, failures.clone()))
+            })
+            .collect())
+    }
+
+    // Response times merged since last call, warm-up included, used to show
+    // live progress while load test is running.
+    pub fn take_live_response_times(&self) -> AppResult<ResponseTimes> {
+        self.live_response_times.take()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LatencyStats {
+    pub requests: usize,
+    pub p50: f64,
+    pub p99: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SecondStats {
+    pub total: LatencyStats,
+    pub errors: usize,
+    pub categories: [LatencyStats; STATUS_CODE_CATEGORIES],
+}
+
+impl SecondStats {
+    fn from_response_times(response_times: &ResponseTimes) -> AppResult<Self> {
+        let mut categories = [LatencyStats::default(); STATUS_CODE_CATEGORIES];
+
+        for status_code_category in StatusCodeCategory::all() {
+            categories[status_code_category as usize] = LatencyStats {
+                requests: response_times.get_requests_count(status_code_category)?,
+                p50: response_times.get_percentile_response_time(status_code_category, 50.0)?,
+                p99: response_times.get_percentile_response_time(status_code_category, 99.0)?,
+            };
+        }
+
+        Ok(Self {
+            total: LatencyStats {
+                requests: response_times.get_total_requests_count()?,
+                p50: response_times.get_total_percentile_response_time(50.0)?,
+                p99: response_times.get_total_percentile_response_time(99.0)?,
+            },
+            errors: response_times.get_errors_count()?,
+            categories,
+        })
+    }
+
+    // Percentiles can't be merged once reduced, so late samples only count.
+    fn add_requests(&mut self, other: &Self) {
+        self.total.requests += other.total.requests;
+        self.errors += other.errors;
+
+        for (category, other_category) in self.categories.iter_mut().zip(&other.categories) {
+            category.requests += other_category.requests;
+        }
+    }
+}
+
+// Keeps memory of long runs at a few numbers per second instead of a set of
+// histograms per second. Samples a recorder holds back for longer than
+// `OPEN_SECONDS` still add to requests and errors of their second.
+#[derive(Default)]
+pub struct TimeSeries {
+    open_seconds: BTreeMap<u64, ResponseTimes>,
+    closed_seconds: Vec<SecondStats>,
+}
+
+impl TimeSeries {
+    pub fn merge<'a, I, J>(&mut self, seconds: I, significant_figures: u8) -> AppResult<()>
+    where
+        I: Iterator<Item = (u64, J)>,
+        J: Iterator<Item = (StatusCodeCategory, &'a Histogram<u64>)>,
+    {
+        for (second, histograms) in seconds {
+            if let Some(second_stats) = self.closed_seconds.get_mut(second as usize) {
+                let response_times = ResponseTimes::new(significant_figures)?;
+
+                response_times.merge(histograms)?;
+                second_stats.add_requests(&SecondStats::from_response_times(&response_times)?);
+
+                continue;
+            }
+
+            match self.open_seconds.entry(second) {
+                Entry::Occupied(entry) => entry.get().merge(histograms)?,
+                Entry::Vacant(entry) => entry
+                    .insert(ResponseTimes::new(significant_figures)?)
+                    .merge(histograms)?,
+            }
+        }
+
+        let last_second = match self.open_seconds.last_key_value() {
+            Some((second, _)) => *second,
+            None => return Ok(()),
+        };
+
+        while let Some(entry) = self.open_seconds.first_entry() {
+            if *entry.key() + OPEN_SECONDS > last_second {
+                break;
+            }
+
+            let (second, response_times) = entry.remove_entry();
+
+            Self::push_second(&mut self.closed_seconds, second, &response_times)?;
+        }
+
+        Ok(())
+    }
+
+    // Seconds nothing completed in are filled with empty stats.
+    fn push_second(
+        seconds: &mut Vec<SecondStats>,
+        second: u64,
+        response_times: &ResponseTimes,
+    ) -> AppResult<()> {
+        seconds.resize_with(second as usize, Default::default);
+        seconds.push(SecondStats::from_response_times(response_times)?);
+
+        Ok(())
+    }
+
+    pub fn get_seconds(&self) -> AppResult<Vec<SecondStats>> {
+        let mut seconds = self.closed_seconds.clone();
+
+        for (second, response_times) in &self.open_seconds {
+            Self::push_second(&mut seconds, *second, response_times)?;
+        }
+
+        Ok(seconds)
+    }
+}
+
+pub struct RateStats {
+    pub min: f64,
+    pub avg: f64,
+    pub max: f64,
+    pub stdev: f64,
+}
+
+impl RateStats {
+    pub fn from_rates(rates: &[f64]) -> Option<Self> {
+        if rates.is_empty() {
+            return None;
+        }
+
+        let avg = rates.iter().sum::<f64>() / rates.len() as f64;
+        let variance =
+            rates.iter().map(|rate| (rate - avg).powi(2)).sum::<f64>() / rates.len() as f64;
+
+        Some(Self {
+            min: rates.iter().copied().fold(f64::INFINITY, f64::min),
+            avg,
+            max: rates.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            stdev: variance.sqrt(),
+        })
+    }
+}
+
+pub struct ResponseTimes {
+    histograms: HashMap<StatusCodeCategory, Mutex<Histogram<u64>>>,
+}
+
+impl ResponseTimes {
+    pub fn new(significant_figures: u8) -> AppResult<Self> {
+        let mut histograms = HashMap::new();
+
+        for status_code_category in StatusCodeCategory::all() {
+            histograms.insert(
+                status_code_category,
+                Mutex::new(Recorder::new_histogram(significant_figures)?),
+            );
+        }
+
+        Ok(Self { histograms })
     }
 
     fn get_responses(
         &self,
         status_code_category: &StatusCodeCategory,
-    ) -> AppResult<MutexGuard<Vec<u128>>> {
-        match self.response_times.get(&status_code_category) {
+    ) -> AppResult<MutexGuard<'_, Histogram<u64>>> {
+        match self.histograms.get(status_code_category) {
             Some(response_times) => match response_times.lock() {
                 Ok(guard) => Ok(guard),
                 Err(_) => Err(ErrorType::InvalidError),
@@ -40,13 +503,67 @@ impl Storage {
         }
     }
 
-    pub fn add_response_time(
-        &self,
-        status_code_category: StatusCodeCategory,
-        response_time: Duration,
-    ) -> AppResult<()> {
-        self.get_responses(&status_code_category)?
-            .push(response_time.as_millis());
+    fn get_all_responses(&self) -> AppResult<Histogram<u64>> {
+        let mut responses =
+            Histogram::new_from(&*self.get_responses(&StatusCodeCategory::Success)?);
+
+        for status_code_category in StatusCodeCategory::all() {
+            match responses.add(&*self.get_responses(&status_code_category)?) {
+                Ok(_) => {}
+                Err(_) => return Err(ErrorType::InvalidError),
+            }
+        }
+
+        Ok(responses)
+    }
+
+    fn take(&self) -> AppResult<Self> {
+        let mut histograms = HashMap::new();
+
+        for status_code_category in StatusCodeCategory::all() {
+            let mut responses = self.get_responses(&status_code_category)?;
+            let empty_responses = Histogram::new_from(&*responses);
+
+            histograms.insert(
+                status_code_category,
+                Mutex::new(mem::replace(&mut *responses, empty_responses)),
+            );
+        }
+
+        Ok(Self { histograms })
+    }
+
+    fn to_millis(micros: f64) -> f64 {
+        micros / 1000.0
+    }
+
+    pub fn merge<'a, I>(&self, histograms: I) -> AppResult<()>
+    where
+        I: Iterator<Item = (StatusCodeCategory, &'a Histogram<u64>)>,
+    {
+        for (status_code_category, response_times) in histograms {
+            match self
+                .get_responses(&status_code_category)?
+                .add(response_times)
+            {
+                Ok(_) => {}
+                Err(_) => return Err(ErrorType::InvalidError),
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn add(&self, response_times: &ResponseTimes) -> AppResult<()> {
+        for status_code_category in StatusCodeCategory::all() {
+            match self
+                .get_responses(&status_code_category)?
+                .add(&*response_times.get_responses(&status_code_category)?)
+            {
+                Ok(_) => {}
+                Err(_) => return Err(ErrorType::InvalidError),
+            }
+        }
 
         Ok(())
     }
@@ -54,110 +571,572 @@ impl Storage {
     pub fn get_avg_response_time(
         &self,
         status_code_category: StatusCodeCategory,
-    ) -> AppResult<usize> {
-        let mut times = 0;
+    ) -> AppResult<f64> {
+        Ok(Self::to_millis(
+            self.get_responses(&status_code_category)?.mean(),
+        ))
+    }
 
-        let mut count = self.get_responses(&status_code_category)?.len();
+    pub fn get_total_avg_response_time(&self) -> AppResult<f64> {
+        Ok(Self::to_millis(self.get_all_responses()?.mean()))
+    }
 
-        if count == 0 {
-            count = 1
-        }
+    pub fn get_requests_count(&self, status_code_category: StatusCodeCategory) -> AppResult<usize> {
+        Ok(self.get_responses(&status_code_category)?.len() as usize)
+    }
 
-        for time in self.get_responses(&status_code_category)?.iter() {
-            times += time
+    // Requests target couldn't handle, client and server errors along with
+    // failed requests and responses failing assertions.
+    pub fn get_errors_count(&self) -> AppResult<usize> {
+        let mut errors_count = 0;
+
+        for status_code_category in [
+            StatusCodeCategory::ClientError,
+            StatusCodeCategory::ServerError,
+            StatusCodeCategory::Failed,
+            StatusCodeCategory::AssertionFailed,
+        ] {
+            errors_count += self.get_requests_count(status_code_category)?;
         }
 
-        Ok(times as usize / count)
+        Ok(errors_count)
     }
 
-    pub fn get_total_avg_response_time(&self) -> AppResult<usize> {
-        let mut total_avg_response_time = 0;
-        let mut non_zores_response_time = 0;
+    // Requests target answered, failed requests never got a response.
+    pub fn get_responses_count(&self) -> AppResult<usize> {
+        Ok(self.get_total_requests_count()?
+            - self.get_requests_count(StatusCodeCategory::Failed)?)
+    }
 
-        let success_avg_response_time = self.get_avg_response_time(StatusCodeCategory::Success)?;
-        let redirectional_avg_response_time =
-            self.get_avg_response_time(StatusCodeCategory::Redirection)?;
-        let client_error_avg_response_time =
-            self.get_avg_response_time(StatusCodeCategory::ClientError)?;
-        let server_error_avg_response_time =
-            self.get_avg_response_time(StatusCodeCategory::ServerError)?;
-        let informational_avg_response_time =
-            self.get_avg_response_time(StatusCodeCategory::Informational)?;
-        let failed_avg_response_time = self.get_avg_response_time(StatusCodeCategory::Failed)?;
+    pub fn get_total_requests_count(&self) -> AppResult<usize> {
+        let mut total_requests_count = 0;
 
-        if success_avg_response_time != 0 {
-            total_avg_response_time += success_avg_response_time;
-            non_zores_response_time += 1;
+        for status_code_category in StatusCodeCategory::all() {
+            total_requests_count += self.get_requests_count(status_code_category)?;
         }
-        if redirectional_avg_response_time != 0 {
-            total_avg_response_time += redirectional_avg_response_time;
-            non_zores_response_time += 1;
+
+        Ok(total_requests_count)
+    }
+
+    pub fn get_min_reponse_time(&self, status_code_category: StatusCodeCategory) -> AppResult<f64> {
+        Ok(Self::to_millis(
+            self.get_responses(&status_code_category)?.min() as f64,
+        ))
+    }
+
+    pub fn get_max_reponse_time(&self, status_code_category: StatusCodeCategory) -> AppResult<f64> {
+        Ok(Self::to_millis(
+            self.get_responses(&status_code_category)?.max() as f64,
+        ))
+    }
+
+    pub fn get_percentile_response_time(
+        &self,
+        status_code_category: StatusCodeCategory,
+        percentile: f64,
+    ) -> AppResult<f64> {
+        Ok(Self::to_millis(
+            self.get_responses(&status_code_category)?
+                .value_at_percentile(percentile) as f64,
+        ))
+    }
+
+    pub fn get_total_percentile_response_time(&self, percentile: f64) -> AppResult<f64> {
+        Ok(Self::to_millis(
+            self.get_all_responses()?.value_at_percentile(percentile) as f64,
+        ))
+    }
+
+    pub fn get_std_deviation_response_time(
+        &self,
+        status_code_category: StatusCodeCategory,
+    ) -> AppResult<f64> {
+        Ok(Self::to_millis(
+            self.get_responses(&status_code_category)?.stdev(),
+        ))
+    }
+
+    pub fn get_total_std_deviation_response_time(&self) -> AppResult<f64> {
+        Ok(Self::to_millis(self.get_all_responses()?.stdev()))
+    }
+
+    pub fn get_total_min_response_time(&self) -> AppResult<f64> {
+        Ok(Self::to_millis(self.get_all_responses()?.min() as f64))
+    }
+
+    pub fn get_total_max_response_time(&self) -> AppResult<f64> {
+        Ok(Self::to_millis(self.get_all_responses()?.max() as f64))
+    }
+
+    // Latency distribution as upper bound of each bucket in milliseconds and
+    // requests count falling into it.
+    pub fn get_total_distribution(&self) -> AppResult<Vec<(f64, u64)>> {
+        let responses = self.get_all_responses()?;
+
+        if responses.is_empty() {
+            return Ok(Vec::new());
         }
-        if client_error_avg_response_time != 0 {
-            total_avg_response_time += client_error_avg_response_time;
-            non_zores_response_time += 1;
+
+        Ok(responses
+            .iter_log(DISTRIBUTION_FIRST_BUCKET, 2.0)
+            .enumerate()
+            .map(|(index, bucket)| {
+                (
+                    Self::to_millis((DISTRIBUTION_FIRST_BUCKET << index) as f64),
+                    bucket.count_since_last_iteration(),
+                )
+            })
+            .collect())
+    }
+}
+
+// Response times per exact status code under the category they were counted
+// in, so codes folded into the same class like 401, 404 and 429 can be told
+// apart.
+pub struct StatusCodeResponseTimes {
+    histograms: Mutex<StatusCodeHistograms>,
+}
+
+impl Default for StatusCodeResponseTimes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StatusCodeResponseTimes {
+    pub fn new() -> Self {
+        Self {
+            histograms: Mutex::new(HashMap::new()),
         }
-        if server_error_avg_response_time != 0 {
-            total_avg_response_time += server_error_avg_response_time;
-            non_zores_response_time += 1;
+    }
+
+    fn get_histograms(&self) -> AppResult<MutexGuard<'_, StatusCodeHistograms>> {
+        match self.histograms.lock() {
+            Ok(guard) => Ok(guard),
+            Err(_) => Err(ErrorType::InvalidError),
         }
-        if informational_avg_response_time != 0 {
-            total_avg_response_time += informational_avg_response_time;
-            non_zores_response_time += 1;
+    }
+
+    fn get_stat<F, T>(
+        &self,
+        status_code_category: StatusCodeCategory,
+        status_code: u16,
+        get_stat: F,
+    ) -> AppResult<T>
+    where
+        F: Fn(&Histogram<u64>) -> T,
+    {
+        match self
+            .get_histograms()?
+            .get(&(status_code_category, status_code))
+        {
+            Some(histogram) => Ok(get_stat(histogram)),
+            None => Err(ErrorType::InvalidError),
         }
-        if failed_avg_response_time != 0 {
-            total_avg_response_time += failed_avg_response_time;
-            non_zores_response_time += 1;
+    }
+
+    pub fn merge<'a, I>(&self, histograms: I) -> AppResult<()>
+    where
+        I: Iterator<Item = ((StatusCodeCategory, u16), &'a Histogram<u64>)>,
+    {
+        let mut self_histograms = self.get_histograms()?;
+
+        for (key, response_times) in histograms {
+            let result = match self_histograms.get_mut(&key) {
+                Some(histogram) => histogram.add(response_times),
+                None => {
+                    self_histograms.insert(key, response_times.clone());
+
+                    Ok(())
+                }
+            };
+
+            if result.is_err() {
+                return Err(ErrorType::InvalidError);
+            }
         }
 
-        Ok(total_avg_response_time / non_zores_response_time)
+        Ok(())
     }
 
-    pub fn get_requests_count(&self, status_code_category: StatusCodeCategory) -> AppResult<usize> {
-        Ok(self.get_responses(&status_code_category)?.len())
+    pub fn get_status_codes(
+        &self,
+        status_code_category: StatusCodeCategory,
+    ) -> AppResult<Vec<u16>> {
+        let mut status_codes: Vec<u16> = self
+            .get_histograms()?
+            .keys()
+            .filter(|(category, _)| *category == status_code_category)
+            .map(|(_, status_code)| *status_code)
+            .collect();
+
+        status_codes.sort_unstable();
+
+        Ok(status_codes)
     }
 
-    pub fn get_total_requests_count(&self) -> AppResult<usize> {
-        let success_requests_count = self.get_requests_count(StatusCodeCategory::Success)?;
-        let redirectional_requests_count =
-            self.get_requests_count(StatusCodeCategory::Redirection)?;
-        let client_error_requests_count =
-            self.get_requests_count(StatusCodeCategory::ClientError)?;
-        let server_error_requests_count =
-            self.get_requests_count(StatusCodeCategory::ServerError)?;
-        let informational_requests_count =
-            self.get_requests_count(StatusCodeCategory::Informational)?;
-        let failed_requests_count = self.get_requests_count(StatusCodeCategory::Failed)?;
-
-        Ok(success_requests_count
-            + redirectional_requests_count
-            + client_error_requests_count
-            + server_error_requests_count
-            + informational_requests_count
-            + failed_requests_count)
+    pub fn get_requests_count(
+        &self,
+        status_code_category: StatusCodeCategory,
+        status_code: u16,
+    ) -> AppResult<usize> {
+        self.get_stat(status_code_category, status_code, |histogram| {
+            histogram.len() as usize
+        })
+    }
+
+    pub fn get_avg_response_time(
+        &self,
+        status_code_category: StatusCodeCategory,
+        status_code: u16,
+    ) -> AppResult<f64> {
+        self.get_stat(status_code_category, status_code, |histogram| {
+            ResponseTimes::to_millis(histogram.mean())
+        })
+    }
+
+    pub fn get_std_deviation_response_time(
+        &self,
+        status_code_category: StatusCodeCategory,
+        status_code: u16,
+    ) -> AppResult<f64> {
+        self.get_stat(status_code_category, status_code, |histogram| {
+            ResponseTimes::to_millis(histogram.stdev())
+        })
     }
 
     pub fn get_min_reponse_time(
         &self,
         status_code_category: StatusCodeCategory,
-    ) -> AppResult<u128> {
-        Ok(self
-            .get_responses(&status_code_category)?
-            .iter()
-            .min()
-            .copied()
-            .unwrap_or(0))
+        status_code: u16,
+    ) -> AppResult<f64> {
+        self.get_stat(status_code_category, status_code, |histogram| {
+            ResponseTimes::to_millis(histogram.min() as f64)
+        })
     }
 
     pub fn get_max_reponse_time(
         &self,
         status_code_category: StatusCodeCategory,
-    ) -> AppResult<u128> {
-        Ok(self
-            .get_responses(&status_code_category)?
-            .iter()
-            .max()
-            .copied()
-            .unwrap_or(0))
+        status_code: u16,
+    ) -> AppResult<f64> {
+        self.get_stat(status_code_category, status_code, |histogram| {
+            ResponseTimes::to_millis(histogram.max() as f64)
+        })
+    }
+
+    pub fn get_percentile_response_time(
+        &self,
+        status_code_category: StatusCodeCategory,
+        status_code: u16,
+        percentile: f64,
+    ) -> AppResult<f64> {
+        self.get_stat(status_code_category, status_code, |histogram| {
+            ResponseTimes::to_millis(histogram.value_at_percentile(percentile) as f64)
+        })
+    }
+}
+
+// Time spent in each phase of requests that got a response, so slowness in
+// getting the first byte can be told apart from slowness in transfer.
+pub struct PhaseResponseTimes {
+    histograms: HashMap<Phase, Mutex<Histogram<u64>>>,
+}
+
+impl PhaseResponseTimes {
+    pub fn new(significant_figures: u8) -> AppResult<Self> {
+        let mut histograms = HashMap::new();
+
+        for phase in Phase::all() {
+            histograms.insert(
+                phase,
+                Mutex::new(Recorder::new_histogram(significant_figures)?),
+            );
+        }
+
+        Ok(Self { histograms })
+    }
+
+    fn get_responses(&self, phase: &Phase) -> AppResult<MutexGuard<'_, Histogram<u64>>> {
+        match self.histograms.get(phase) {
+            Some(response_times) => match response_times.lock() {
+                Ok(guard) => Ok(guard),
+                Err(_) => Err(ErrorType::InvalidError),
+            },
+            None => Err(ErrorType::InvalidError),
+        }
+    }
+
+    pub fn merge<'a, I>(&self, histograms: I) -> AppResult<()>
+    where
+        I: Iterator<Item = (Phase, &'a Histogram<u64>)>,
+    {
+        for (phase, response_times) in histograms {
+            match self.get_responses(&phase)?.add(response_times) {
+                Ok(_) => {}
+                Err(_) => return Err(ErrorType::InvalidError),
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn get_requests_count(&self, phase: Phase) -> AppResult<usize> {
+        Ok(self.get_responses(&phase)?.len() as usize)
+    }
+
+    pub fn get_avg_response_time(&self, phase: Phase) -> AppResult<f64> {
+        Ok(ResponseTimes::to_millis(self.get_responses(&phase)?.mean()))
+    }
+
+    pub fn get_std_deviation_response_time(&self, phase: Phase) -> AppResult<f64> {
+        Ok(ResponseTimes::to_millis(
+            self.get_responses(&phase)?.stdev(),
+        ))
+    }
+
+    pub fn get_min_reponse_time(&self, phase: Phase) -> AppResult<f64> {
+        Ok(ResponseTimes::to_millis(
+            self.get_responses(&phase)?.min() as f64
+        ))
+    }
+
+    pub fn get_max_reponse_time(&self, phase: Phase) -> AppResult<f64> {
+        Ok(ResponseTimes::to_millis(
+            self.get_responses(&phase)?.max() as f64
+        ))
+    }
+
+    pub fn get_percentile_response_time(&self, phase: Phase, percentile: f64) -> AppResult<f64> {
+        Ok(ResponseTimes::to_millis(
+            self.get_responses(&phase)?.value_at_percentile(percentile) as f64,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Method;
+
+    use super::*;
+    use crate::recorder::Sample;
+
+    fn record(recorder: &mut Recorder, second: u64, status_code_category: StatusCodeCategory) {
+        recorder
+            .add_second_response_time(second, status_code_category, Duration::from_millis(10))
+            .unwrap();
+    }
+
+    #[test]
+    fn time_series_reduces_old_seconds_and_counts_late_samples() {
+        let mut time_series = TimeSeries::default();
+        let mut recorder = Recorder::new(3);
+
+        record(&mut recorder, 0, StatusCodeCategory::Success);
+        record(&mut recorder, 2, StatusCodeCategory::ServerError);
+        record(&mut recorder, 2 + OPEN_SECONDS, StatusCodeCategory::Success);
+        time_series
+            .merge(recorder.get_second_response_times(), 3)
+            .unwrap();
+
+        assert_eq!(time_series.closed_seconds.len(), 3);
+        assert_eq!(time_series.open_seconds.len(), 1);
+
+        let mut late_recorder = Recorder::new(3);
+
+        record(&mut late_recorder, 2, StatusCodeCategory::Failed);
+        time_series
+            .merge(late_recorder.get_second_response_times(), 3)
+            .unwrap();
+
+        let seconds = time_series.get_seconds().unwrap();
+
+        assert_eq!(seconds.len(), 3 + OPEN_SECONDS as usize);
+        assert_eq!(seconds[0].total.requests, 1);
+        assert_eq!(seconds[1].total.requests, 0);
+        assert_eq!(seconds[2].total.requests, 2);
+        assert_eq!(seconds[2].errors, 2);
+        assert_eq!(
+            seconds[2].categories[StatusCodeCategory::Failed as usize].requests,
+            1
+        );
+        assert!(seconds[2].total.p99 > 9.0);
+    }
+
+    fn storage<F>(configure: F) -> Storage
+    where
+        F: FnOnce(&mut Arguments),
+    {
+        let mut arguments = Arguments::new("http://localhost:5500/".to_owned(), Method::GET);
+
+        configure(&mut arguments);
+
+        Storage::from_arguments(&arguments).unwrap()
+    }
+
+    fn assert_rate_stats(rate_stats: Option<RateStats>, min: f64, avg: f64, max: f64, stdev: f64) {
+        let rate_stats = rate_stats.unwrap();
+
+        for (actual, expected) in [
+            (rate_stats.min, min),
+            (rate_stats.avg, avg),
+            (rate_stats.max, max),
+            (rate_stats.stdev, stdev),
+        ] {
+            assert!(
+                (actual - expected).abs() < 1e-9,
+                "{} != {}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn rate_stats_spread_rates() {
+        assert!(RateStats::from_rates(&[]).is_none());
+        assert_rate_stats(
+            RateStats::from_rates(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]),
+            2.0,
+            5.0,
+            9.0,
+            2.0,
+        );
+    }
+
+    #[test]
+    fn second_rate_stats_leave_out_partial_last_second() {
+        let storage = storage(|_| {});
+        let mut recorder = Recorder::new(3);
+
+        for second in [0, 0, 0, 2, 3] {
+            record(&mut recorder, second, StatusCodeCategory::Success);
+        }
+        storage.merge(&recorder).unwrap();
+        storage
+            .set_elapsed_time(Duration::from_millis(3500))
+            .unwrap();
+
+        // Second 1 had no requests, second 3 is cut short.
+        assert_rate_stats(
+            storage.get_second_rate_stats().unwrap(),
+            0.0,
+            4.0 / 3.0,
+            3.0,
+            (14.0f64 / 9.0).sqrt(),
+        );
+    }
+
+    fn add_worker_requests(storage: &Storage, worker_requests: &[u64]) {
+        let mut recorder = Recorder::new(3);
+
+        for (worker, requests) in worker_requests.iter().enumerate() {
+            for _ in 0..*requests {
+                recorder
+                    .add_sample(&Sample {
+                        worker,
+                        ..Sample::default()
+                    })
+                    .unwrap();
+            }
+        }
+        storage.merge(&recorder).unwrap();
+    }
+
+    #[test]
+    fn worker_rate_stats_use_whole_run() {
+        let storage = storage(|arguments| arguments.connections = 2);
+
+        add_worker_requests(&storage, &[10, 30]);
+        storage.set_elapsed_time(Duration::from_secs(10)).unwrap();
+
+        assert_rate_stats(storage.get_worker_rate_stats().unwrap(), 1.0, 2.0, 3.0, 1.0);
+    }
+
+    #[test]
+    fn worker_rate_stats_use_time_profile_kept_connections_active() {
+        let storage = storage(|arguments| {
+            arguments.profile = Some(Profile::parse("ramp:10:2,hold:10:2").unwrap());
+            arguments.connections = 3;
+        });
+
+        // Connection 0 is active for 15 seconds and connection 1 for 10,
+        // connection 2 is never activated.
+        add_worker_requests(&storage, &[30, 20, 0]);
+        storage.set_elapsed_time(Duration::from_secs(20)).unwrap();
+
+        assert_rate_stats(storage.get_worker_rate_stats().unwrap(), 2.0, 2.0, 2.0, 0.0);
+    }
+
+    fn assert_millis(actual: AppResult<f64>, expected: f64) {
+        let actual = actual.unwrap();
+
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn response_times_report_known_samples_in_milliseconds() {
+        let storage = storage(|_| {});
+        let mut recorder = Recorder::new(3);
+
+        // 0.1 to 1 ms successes, recorded with microsecond resolution, and two
+        // slower client errors.
+        for (micros, status_code_category) in (1..=10)
+            .map(|index| (index * 100, StatusCodeCategory::Success))
+            .chain([
+                (1500, StatusCodeCategory::ClientError),
+                (2000, StatusCodeCategory::ClientError),
+            ])
+        {
+            recorder
+                .add_sample(&Sample {
+                    status_code_category,
+                    response_time: Duration::from_micros(micros),
+                    ..Sample::default()
+                })
+                .unwrap();
+        }
+        storage.merge(&recorder).unwrap();
+
+        let response_times = storage.get_response_times();
+        let success = StatusCodeCategory::Success;
+
+        assert_eq!(response_times.get_requests_count(success).unwrap(), 10);
+        assert_millis(response_times.get_avg_response_time(success), 0.55);
+        assert_millis(response_times.get_min_reponse_time(success), 0.1);
+        assert_millis(response_times.get_max_reponse_time(success), 1.0);
+        assert_millis(
+            response_times.get_percentile_response_time(success, 50.0),
+            0.5,
+        );
+        assert_millis(
+            response_times.get_percentile_response_time(success, 90.0),
+            0.9,
+        );
+        assert_millis(
+            response_times.get_percentile_response_time(success, 99.0),
+            1.0,
+        );
+        assert_millis(
+            response_times.get_std_deviation_response_time(success),
+            0.287228132,
+        );
+
+        assert_eq!(response_times.get_total_requests_count().unwrap(), 12);
+        assert_eq!(response_times.get_errors_count().unwrap(), 2);
+        assert_millis(response_times.get_total_avg_response_time(), 0.75);
+        assert_millis(response_times.get_total_min_response_time(), 0.1);
+        assert_millis(response_times.get_total_max_response_time(), 2.0);
+        assert_millis(response_times.get_total_percentile_response_time(50.0), 0.6);
+        assert_millis(response_times.get_total_percentile_response_time(95.0), 2.0);
+        assert_millis(
+            response_times.get_total_std_deviation_response_time(),
+            0.528362249,
+        );
     }
 }
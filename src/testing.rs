@@ -0,0 +1,9 @@
+// Helpers shared by tests of several modules.
+
+use std::{env, path::PathBuf, process};
+
+// Path in system temp directory unique to this test process, tests pass
+// distinct names so they can run in parallel.
+pub fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("cicadas-{}-{}", process::id(), name))
+}
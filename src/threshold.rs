@@ -0,0 +1,231 @@
+use crate::{
+    errors::{AppResult, ErrorType},
+    storage::Storage,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Metric {
+    Percentile(f64),
+    Avg,
+    Min,
+    Max,
+    Stdev,
+    ErrorRate,
+    RequestsPerSecond,
+    Requests,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+// Pass/fail condition on a result metric, like `p99<250ms`, `error_rate<1%`
+// or `rps>1000`, checked once load test finishes. Latencies are compared in
+// milliseconds and error rate in percent.
+#[derive(Debug, Clone)]
+pub struct Threshold {
+    pub expression: String,
+    metric: Metric,
+    operator: Operator,
+    value: f64,
+}
+
+impl Threshold {
+    pub fn parse(expression: &str) -> AppResult<Self> {
+        let expression = expression.trim();
+        let (position, operator, length) = match expression.find(['<', '>']) {
+            Some(position) => match &expression[position..] {
+                rest if rest.starts_with("<=") => (position, Operator::LessOrEqual, 2),
+                rest if rest.starts_with(">=") => (position, Operator::GreaterOrEqual, 2),
+                rest if rest.starts_with('<') => (position, Operator::Less, 1),
+                _ => (position, Operator::Greater, 1),
+            },
+            None => return Err(ErrorType::InvalidThreshold),
+        };
+
+        let metric = Self::parse_metric(expression[..position].trim())?;
+        let value = Self::parse_value(metric, expression[position + length..].trim())?;
+
+        Ok(Self {
+            expression: expression.to_owned(),
+            metric,
+            operator,
+            value,
+        })
+    }
+
+    fn parse_metric(metric: &str) -> AppResult<Metric> {
+        match metric.to_lowercase().as_str() {
+            "avg" => Ok(Metric::Avg),
+            "min" => Ok(Metric::Min),
+            "max" => Ok(Metric::Max),
+            "stdev" => Ok(Metric::Stdev),
+            "error_rate" => Ok(Metric::ErrorRate),
+            "rps" => Ok(Metric::RequestsPerSecond),
+            "requests" => Ok(Metric::Requests),
+            metric => match metric.strip_prefix('p').map(str::parse::<f64>) {
+                Some(Ok(percentile)) if (0.0..=100.0).contains(&percentile) => {
+                    Ok(Metric::Percentile(percentile))
+                }
+                _ => Err(ErrorType::InvalidThreshold),
+            },
+        }
+    }
+
+    fn parse_value(metric: Metric, value: &str) -> AppResult<f64> {
+        let (number, multiplier) = match metric {
+            Metric::Percentile(_) | Metric::Avg | Metric::Min | Metric::Max | Metric::Stdev => {
+                if let Some(number) = value.strip_suffix("ms") {
+                    (number, 1.0)
+                } else if let Some(number) = value.strip_suffix('s') {
+                    (number, 1000.0)
+                } else {
+                    (value, 1.0)
+                }
+            }
+            Metric::ErrorRate => (value.strip_suffix('%').unwrap_or(value), 1.0),
+            Metric::RequestsPerSecond | Metric::Requests => (value, 1.0),
+        };
+
+        match number.trim().parse::<f64>() {
+            Ok(number) if number.is_finite() => Ok(number * multiplier),
+            _ => Err(ErrorType::InvalidThreshold),
+        }
+    }
+
+    // Latencies, rates and error rate have no value without requests, so
+    // thresholds on them fail instead of passing on zeros.
+    pub fn get_actual_value(&self, storage: &Storage) -> AppResult<Option<f64>> {
+        let response_times = storage.get_response_times();
+        let total_requests_count = response_times.get_total_requests_count()?;
+
+        if total_requests_count == 0 && self.metric != Metric::Requests {
+            return Ok(None);
+        }
+
+        let actual_value = match self.metric {
+            Metric::Percentile(percentile) => {
+                response_times.get_total_percentile_response_time(percentile)
+            }
+            Metric::Avg => response_times.get_total_avg_response_time(),
+            Metric::Min => response_times.get_total_min_response_time(),
+            Metric::Max => response_times.get_total_max_response_time(),
+            Metric::Stdev => response_times.get_total_std_deviation_response_time(),
+            Metric::ErrorRate => {
+                Ok(response_times.get_errors_count()? as f64 * 100.0 / total_requests_count as f64)
+            }
+            Metric::RequestsPerSecond => storage.get_requests_per_second(total_requests_count),
+            Metric::Requests => Ok(total_requests_count as f64),
+        }?;
+
+        Ok(Some(actual_value))
+    }
+
+    // Formats a value of this threshold's metric with the unit it's compared
+    // in.
+    pub fn format_value(&self, value: f64) -> String {
+        match self.metric {
+            Metric::Percentile(_) | Metric::Avg | Metric::Min | Metric::Max | Metric::Stdev => {
+                format!("{:.2} ms", value)
+            }
+            Metric::ErrorRate => format!("{:.2}%", value),
+            Metric::RequestsPerSecond => format!("{:.2} req/s", value),
+            Metric::Requests => format!("{:.0}", value),
+        }
+    }
+
+    pub fn is_passed(&self, actual_value: Option<f64>) -> bool {
+        match (self.operator, actual_value) {
+            (_, None) => false,
+            (Operator::Less, Some(actual_value)) => actual_value < self.value,
+            (Operator::LessOrEqual, Some(actual_value)) => actual_value <= self.value,
+            (Operator::Greater, Some(actual_value)) => actual_value > self.value,
+            (Operator::GreaterOrEqual, Some(actual_value)) => actual_value >= self.value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Method;
+
+    use super::*;
+    use crate::app::Arguments;
+
+    fn parse(expression: &str) -> Threshold {
+        Threshold::parse(expression).unwrap()
+    }
+
+    #[test]
+    fn parses_operators() {
+        assert_eq!(parse("p99<250").operator, Operator::Less);
+        assert_eq!(parse("p99<=250").operator, Operator::LessOrEqual);
+        assert_eq!(parse("rps>1000").operator, Operator::Greater);
+        assert_eq!(parse("rps >= 1000").operator, Operator::GreaterOrEqual);
+    }
+
+    #[test]
+    fn parses_value_suffixes() {
+        assert_eq!(parse("p99<250ms").value, 250.0);
+        assert_eq!(parse("avg<1.5s").value, 1500.0);
+        assert_eq!(parse("max<250").value, 250.0);
+        assert_eq!(parse("error_rate<1%").value, 1.0);
+        assert_eq!(parse("error_rate<0.5").value, 0.5);
+        assert_eq!(parse("requests>=100").value, 100.0);
+    }
+
+    #[test]
+    fn parses_fractional_percentiles() {
+        assert_eq!(parse("p99.9<500ms").metric, Metric::Percentile(99.9));
+        assert_eq!(parse("P50<100ms").metric, Metric::Percentile(50.0));
+    }
+
+    #[test]
+    fn rejects_invalid_thresholds() {
+        assert!(Threshold::parse("p101<250ms").is_err());
+        assert!(Threshold::parse("p99 250ms").is_err());
+        assert!(Threshold::parse("p99<").is_err());
+        assert!(Threshold::parse("p99<ms").is_err());
+        assert!(Threshold::parse("<250ms").is_err());
+        assert!(Threshold::parse("rps>1000ms").is_err());
+        assert!(Threshold::parse("p99<inf").is_err());
+        assert!(Threshold::parse("avg<NaN").is_err());
+        assert!(Threshold::parse("rps>-infinity").is_err());
+    }
+
+    #[test]
+    fn fails_without_actual_value() {
+        assert!(parse("p99<250ms").is_passed(Some(100.0)));
+        assert!(!parse("p99<250ms").is_passed(Some(250.0)));
+        assert!(parse("p99<=250ms").is_passed(Some(250.0)));
+        assert!(!parse("p99<250ms").is_passed(None));
+        assert!(!parse("rps>=0").is_passed(None));
+    }
+
+    #[test]
+    fn has_no_actual_value_without_requests() {
+        let arguments = Arguments::new("http://localhost:5500/".to_owned(), Method::GET);
+        let storage = Storage::from_arguments(&arguments).unwrap();
+
+        for expression in ["p99<250ms", "avg<100", "error_rate<1%", "rps>0"] {
+            assert_eq!(parse(expression).get_actual_value(&storage).unwrap(), None);
+        }
+
+        assert_eq!(
+            parse("requests>=0").get_actual_value(&storage).unwrap(),
+            Some(0.0)
+        );
+    }
+
+    #[test]
+    fn formats_values_with_metric_unit() {
+        assert_eq!(parse("p99<250ms").format_value(12.345), "12.35 ms");
+        assert_eq!(parse("error_rate<1%").format_value(0.5), "0.50%");
+        assert_eq!(parse("rps>1000").format_value(1200.0), "1200.00 req/s");
+        assert_eq!(parse("requests>10").format_value(42.0), "42");
+    }
+}
@@ -0,0 +1,101 @@
+use std::{
+    sync::{
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::errors::{AppResult, ErrorType};
+
+// Hands items over from connections to a background thread through a bounded
+// channel, so formatting and disk writes never happen on connections.
+pub struct BackgroundWriter<T> {
+    sender: Mutex<Option<SyncSender<T>>>,
+    handle: Mutex<Option<JoinHandle<AppResult<()>>>>,
+}
+
+impl<T: Send + 'static> BackgroundWriter<T> {
+    pub fn spawn<F>(capacity: usize, write: F) -> Self
+    where
+        F: FnOnce(Receiver<T>) -> AppResult<()> + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+
+        let handle = thread::spawn(move || write(receiver));
+
+        Self {
+            sender: Mutex::new(Some(sender)),
+            handle: Mutex::new(Some(handle)),
+        }
+    }
+
+    // Every connection takes its own sender once, so sending never contends
+    // on a shared lock.
+    pub fn get_sender(&self) -> AppResult<BackgroundSender<T>> {
+        match self.sender.lock() {
+            Ok(sender) => match sender.as_ref() {
+                Some(sender) => Ok(BackgroundSender {
+                    sender: sender.clone(),
+                }),
+                None => Err(ErrorType::InvalidError),
+            },
+            Err(_) => Err(ErrorType::InvalidError),
+        }
+    }
+
+    // Closes the channel and waits for every pending item to be written.
+    pub fn finish(&self) -> AppResult<()> {
+        match self.sender.lock() {
+            Ok(mut sender) => drop(sender.take()),
+            Err(_) => return Err(ErrorType::InvalidError),
+        }
+
+        let handle = match self.handle.lock() {
+            Ok(mut handle) => handle.take(),
+            Err(_) => return Err(ErrorType::InvalidError),
+        };
+
+        match handle.map(|handle| handle.join()) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(ErrorType::InvalidError),
+            None => Ok(()),
+        }
+    }
+}
+
+pub struct BackgroundSender<T> {
+    sender: SyncSender<T>,
+}
+
+impl<T> Clone for BackgroundSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+// Sending only fails once background thread stopped, which happens when it
+// couldn't write, so callers report it as their write error.
+impl<T: Send + 'static> BackgroundSender<T> {
+    pub fn send(&self, item: T) -> bool {
+        self.sender.send(item).is_ok()
+    }
+
+    // Async connections can't block a runtime worker while channel is full,
+    // they wait for room on the blocking pool instead.
+    pub async fn send_async(&self, item: T) -> bool {
+        let item = match self.sender.try_send(item) {
+            Ok(_) => return true,
+            Err(TrySendError::Full(item)) => item,
+            Err(TrySendError::Disconnected(_)) => return false,
+        };
+        let sender = self.sender.clone();
+
+        matches!(
+            tokio::task::spawn_blocking(move || sender.send(item)).await,
+            Ok(Ok(_))
+        )
+    }
+}